@@ -1,6 +1,8 @@
+use std::collections::HashMap;
+
 use itertools::{repeat_n, Itertools};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum Operator {
     Add,
     Mul,
@@ -17,6 +19,23 @@ impl Operator {
                 .expect("Failed to parse concatenated value"),
         }
     }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Operator::Add => "+",
+            Operator::Mul => "*",
+            Operator::Cat => "||",
+        }
+    }
+
+    /// Like `apply`, but returns `None` instead of overflowing.
+    fn checked_apply(self, acc: i64, x: i64) -> Option<i64> {
+        match self {
+            Operator::Add => acc.checked_add(x),
+            Operator::Mul => acc.checked_mul(x),
+            Operator::Cat => format!("{}{}", acc, x).parse().ok(),
+        }
+    }
 }
 
 /// Reads equations from the input string.
@@ -66,6 +85,107 @@ fn satisfies_equation(test_value: i64, numbers: &[i64], operators: &[Operator])
         })
 }
 
+/// Counts, per equation, how many operator sequences produce its test value.
+fn compute_counts(equations: &[(i64, Vec<i64>)], operators: &[Operator]) -> Vec<u64> {
+    equations
+        .iter()
+        .map(|(test_value, numbers)| SolutionCounter::new(*test_value, numbers, operators).count())
+        .collect()
+}
+
+/// Memoized DP over `(index, accumulator)` states.
+///
+/// `ways(i, acc)` is the number of operator sequences for `numbers[i..]` that turn
+/// `acc` into the test value. Equal intermediate values share one entry, so the
+/// work is bounded by the number of distinct accumulators rather than by
+/// `operators.len() ^ (numbers.len() - 1)`.
+struct SolutionCounter<'a> {
+    test_value: i64,
+    numbers: &'a [i64],
+    operators: &'a [Operator],
+    // With only positive numbers, the first included, no operator can shrink
+    // the accumulator, so anything above the test value is a dead end.
+    monotone: bool,
+    memo: HashMap<(usize, i64), u64>,
+}
+
+impl<'a> SolutionCounter<'a> {
+    fn new(test_value: i64, numbers: &'a [i64], operators: &'a [Operator]) -> Self {
+        SolutionCounter {
+            test_value,
+            numbers,
+            operators,
+            monotone: numbers.iter().all(|&x| x >= 1),
+            memo: HashMap::new(),
+        }
+    }
+
+    fn count(&mut self) -> u64 {
+        match self.numbers.first() {
+            Some(&first) => self.ways(1, first),
+            None => 0,
+        }
+    }
+
+    fn ways(&mut self, index: usize, acc: i64) -> u64 {
+        if index == self.numbers.len() {
+            return (acc == self.test_value) as u64;
+        }
+        if self.monotone && acc > self.test_value {
+            return 0;
+        }
+        if let Some(&ways) = self.memo.get(&(index, acc)) {
+            return ways;
+        }
+
+        let x = self.numbers[index];
+        let ways = self
+            .operators
+            .iter()
+            .filter_map(|operator| operator.checked_apply(acc, x))
+            .map(|next| self.ways(index + 1, next))
+            .sum();
+
+        self.memo.insert((index, acc), ways);
+        ways
+    }
+}
+
+/// Lazily yields every operator sequence that produces `test_value`.
+///
+/// The DP table from `SolutionCounter` is used to skip branches with no
+/// solutions, so each step of the walk leads to at least one result.
+fn solutions<'a>(
+    test_value: i64,
+    numbers: &'a [i64],
+    operators: &'a [Operator],
+) -> impl Iterator<Item = Vec<Operator>> + 'a {
+    let mut counter = SolutionCounter::new(test_value, numbers, operators);
+    let mut stack = Vec::new();
+    if counter.count() > 0 {
+        stack.push((1, numbers[0], Vec::new()));
+    }
+
+    std::iter::from_fn(move || {
+        while let Some((index, acc, sequence)) = stack.pop() {
+            if index == numbers.len() {
+                return Some(sequence);
+            }
+            // push in reverse so that solutions come out in operator order
+            for &operator in operators.iter().rev() {
+                if let Some(next) = operator.checked_apply(acc, numbers[index]) {
+                    if counter.ways(index + 1, next) > 0 {
+                        let mut sequence = sequence.clone();
+                        sequence.push(operator);
+                        stack.push((index + 1, next, sequence));
+                    }
+                }
+            }
+        }
+        None
+    })
+}
+
 fn main() {
     let input = std::fs::read_to_string("input.txt").expect("Failed to read input file");
     let equations = read_equations(&input);
@@ -79,4 +199,101 @@ fn main() {
     let operators_part2 = vec![Operator::Add, Operator::Mul, Operator::Cat];
     let sum_part2 = compute_sum(&equations, &operators_part2);
     println!("part2: {}", sum_part2);
+
+    let counts = compute_counts(&equations, &operators_part2);
+    println!("part2 operator assignments: {}", counts.iter().sum::<u64>());
+
+    // Show the equation with the most ways to reach its test value
+    if let Some(((test_value, numbers), count)) = equations
+        .iter()
+        .zip(&counts)
+        .filter(|(_, &count)| count > 0)
+        .max_by_key(|(_, &count)| count)
+    {
+        println!("{} solutions for {}:", count, test_value);
+        for sequence in solutions(*test_value, numbers, &operators_part2) {
+            let expression = numbers[1..]
+                .iter()
+                .zip(&sequence)
+                .fold(numbers[0].to_string(), |acc, (x, operator)| {
+                    format!("{} {} {}", acc, operator.symbol(), x)
+                });
+            println!("  {}", expression);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_test_input() -> Vec<(i64, Vec<i64>)> {
+        let input = std::fs::read_to_string("test.txt").expect("Failed to read test file");
+        read_equations(&input)
+    }
+
+    #[test]
+    fn test_compute_sum() {
+        let equations = read_test_input();
+
+        assert_eq!(
+            compute_sum(&equations, &[Operator::Add, Operator::Mul]),
+            3749
+        );
+        assert_eq!(
+            compute_sum(&equations, &[Operator::Add, Operator::Mul, Operator::Cat]),
+            11387
+        );
+    }
+
+    #[test]
+    fn test_compute_counts() {
+        let equations = read_test_input();
+
+        let counts = compute_counts(&equations, &[Operator::Add, Operator::Mul]);
+        assert_eq!(counts, vec![1, 2, 0, 0, 0, 0, 0, 0, 1]);
+
+        let counts = compute_counts(&equations, &[Operator::Add, Operator::Mul, Operator::Cat]);
+        assert_eq!(counts, vec![1, 2, 0, 1, 1, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn test_solutions() {
+        let equations = read_test_input();
+        let operators = [Operator::Add, Operator::Mul, Operator::Cat];
+
+        for ((test_value, numbers), count) in
+            equations.iter().zip(compute_counts(&equations, &operators))
+        {
+            let found: Vec<_> = solutions(*test_value, numbers, &operators).collect();
+            assert_eq!(found.len() as u64, count);
+            for sequence in found {
+                let result = numbers[1..]
+                    .iter()
+                    .zip(sequence.iter())
+                    .fold(numbers[0], |acc, (&x, &operator)| operator.apply(acc, x));
+                assert_eq!(result, *test_value);
+            }
+        }
+
+        let found: Vec<_> = solutions(3267, &[81, 40, 27], &operators).collect();
+        assert_eq!(
+            found,
+            vec![
+                vec![Operator::Add, Operator::Mul],
+                vec![Operator::Mul, Operator::Add]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_negative_first_number() {
+        let operators = [Operator::Add, Operator::Mul, Operator::Cat];
+
+        // the accumulator starts above the test value and multiplying brings
+        // it down, as does concatenating a digit to a negative number
+        assert_eq!(SolutionCounter::new(-6, &[-2, 3], &operators).count(), 1);
+        assert_eq!(SolutionCounter::new(-25, &[-2, 5], &operators).count(), 1);
+        assert_eq!(solutions(-6, &[-2, 3], &operators).count(), 1);
+    }
 }
@@ -1,77 +1,510 @@
 use std::{
     collections::{HashMap, HashSet},
-    fs,
+    env,
+    error::Error,
+    fmt, fs,
 };
 
 use num::integer::gcd;
 
-fn main() {
-    let input = fs::read_to_string("input.txt").expect("Unable to read file");
+/// A point in `N`-dimensional space; `[x, y]` for the puzzle grid.
+type Pos<const N: usize> = [i32; N];
 
-    let map: HashMap<(i32, i32), char> = input
-        .lines()
-        .enumerate()
-        .flat_map(|(y, line)| {
-            line.chars()
-                .enumerate()
-                .map(move |(x, c)| ((x as i32, y as i32), c))
-        })
-        .collect();
+fn add<const N: usize>(a: Pos<N>, b: Pos<N>) -> Pos<N> {
+    std::array::from_fn(|i| a[i] + b[i])
+}
 
-    let mut anti_nodes = HashSet::new();
+fn checked_add<const N: usize>(a: Pos<N>, b: Pos<N>) -> Option<Pos<N>> {
+    let mut sum = a;
+    for (c, d) in sum.iter_mut().zip(b) {
+        *c = c.checked_add(d)?;
+    }
+    Some(sum)
+}
 
-    for (pos1, frequency) in map.iter().filter(|(_, &c)| c != '.') {
-        for (pos2, _) in map
-            .iter()
-            .filter(|(pos2, &c)| c == *frequency && pos1 != *pos2)
-        {
-            let dir = (pos2.0 - pos1.0, pos2.1 - pos1.1);
+fn sub<const N: usize>(a: Pos<N>, b: Pos<N>) -> Pos<N> {
+    std::array::from_fn(|i| a[i] - b[i])
+}
+
+fn scale<const N: usize>(a: Pos<N>, k: i32) -> Pos<N> {
+    a.map(|c| c * k)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct PointsError {
+    /// Line number, counting from 1
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for PointsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
 
-            let anti_node = (pos2.0 + dir.0, pos2.1 + dir.1);
+impl Error for PointsError {}
+
+/// Most harmonics on either side of a pair that an unbounded map allows.
+const MAX_UNBOUNDED_HARMONICS: usize = 10_000;
+
+/// How antinodes are placed on the line through a pair of same-frequency antennas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AntinodeRule {
+    /// Points outside the pair that are `far` times as far from one antenna as
+    /// `near` times from the other, e.g. `2:1` for part 1. Points that do not fall
+    /// on integer coordinates are skipped.
+    Ratio { far: i32, near: i32 },
+    /// Every grid point on the line through the pair, including the antennas.
+    /// Needs a bounded map.
+    Resonant,
+    /// Like `Resonant`, but only up to the given number of gcd-reduced steps
+    /// beyond either antenna. On an unbounded map the number may be at most
+    /// `MAX_UNBOUNDED_HARMONICS`.
+    Harmonics(usize),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum AntinodeError {
+    /// The rule places too many antinodes, or infinitely many, on an
+    /// unbounded map
+    Unbounded(AntinodeRule),
+    /// An antinode lies beyond the range of the coordinates
+    OutOfRange,
+}
+
+impl fmt::Display for AntinodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AntinodeError::Unbounded(rule) => write!(
+                f,
+                "{:?} needs a bounded map, or at most {} harmonics",
+                rule, MAX_UNBOUNDED_HARMONICS
+            ),
+            AntinodeError::OutOfRange => write!(f, "Antinode coordinates out of range"),
+        }
+    }
+}
+
+impl Error for AntinodeError {}
+
+struct AntennaMap<const N: usize> {
+    antennas: HashMap<char, Vec<Pos<N>>>,
+    /// Size of the box along each axis, or `None` if antinodes may lie anywhere.
+    bounds: Option<Pos<N>>,
+}
 
-            if map.contains_key(&anti_node) {
-                anti_nodes.insert(anti_node);
+impl AntennaMap<2> {
+    /// Parses the puzzle's character grid, bounded by the grid size.
+    fn from_input(input: &str) -> Self {
+        let mut antennas: HashMap<char, Vec<Pos<2>>> = HashMap::new();
+        let mut width = 0;
+        let mut height = 0;
+
+        for (y, line) in input.lines().enumerate() {
+            height = y as i32 + 1;
+            width = width.max(line.len() as i32);
+            for (x, c) in line.chars().enumerate() {
+                if c != '.' {
+                    antennas.entry(c).or_default().push([x as i32, y as i32]);
+                }
+            }
+        }
+
+        AntennaMap {
+            antennas,
+            bounds: Some([width, height]),
+        }
+    }
+}
+
+impl<const N: usize> AntennaMap<N> {
+    /// Parses one antenna per line as `N` comma-separated coordinates followed by
+    /// its frequency, e.g. `1,2,3,A` in 3D.
+    ///
+    /// An optional first line `bounds: 10,10,10` limits the field to the box
+    /// `0..10` along each axis; without it the field is unbounded. Two antennas
+    /// of the same frequency at the same point are an error, as the line
+    /// through them is undefined.
+    fn from_points(input: &str) -> Result<Self, PointsError> {
+        let mut antennas: HashMap<char, Vec<Pos<N>>> = HashMap::new();
+        let mut bounds = None;
+
+        for (index, line) in input.lines().enumerate() {
+            let error = |message: String| PointsError {
+                line: index + 1,
+                message,
+            };
+            if line.trim().is_empty() {
+                continue;
             }
 
-            let anti_node = (pos1.0 - dir.0, pos1.1 - dir.1);
+            if let Some(size) = line.strip_prefix("bounds:") {
+                if index > 0 {
+                    return Err(error("Bounds must be on the first line".to_string()));
+                }
+                let size: Vec<&str> = size.split(',').collect();
+                bounds = Some(parse_coords(&size).map_err(error)?);
+                continue;
+            }
+
+            let parts: Vec<&str> = line.split(',').collect();
+            let (frequency, coords) = parts.split_last().unwrap();
+            let pos = parse_coords(coords).map_err(error)?;
+            let frequency = frequency
+                .trim()
+                .chars()
+                .next()
+                .ok_or_else(|| error("Missing frequency".to_string()))?;
 
-            if map.contains_key(&anti_node) {
-                anti_nodes.insert(anti_node);
+            let positions = antennas.entry(frequency).or_default();
+            if positions.contains(&pos) {
+                return Err(error(format!(
+                    "Duplicate antenna {} at {:?}",
+                    frequency, pos
+                )));
             }
+            positions.push(pos);
+        }
+
+        Ok(AntennaMap { antennas, bounds })
+    }
+
+    /// Drops the map bounds so antinodes are no longer clipped to the box.
+    fn unbounded(self) -> Self {
+        AntennaMap {
+            bounds: None,
+            ..self
         }
     }
 
-    println!("Part 1: {}", anti_nodes.len());
+    fn contains(&self, pos: Pos<N>) -> bool {
+        match self.bounds {
+            Some(size) => pos.iter().zip(size).all(|(&c, size)| c >= 0 && c < size),
+            None => true,
+        }
+    }
 
-    let mut anti_nodes = HashSet::new();
+    /// Computes the antinodes of every frequency under the given rule.
+    ///
+    /// On an unbounded map, `AntinodeRule::Resonant` and harmonics beyond
+    /// `MAX_UNBOUNDED_HARMONICS` are an error, as there would be infinitely or
+    /// impractically many antinodes.
+    fn antinodes(
+        &self,
+        rule: AntinodeRule,
+    ) -> Result<HashMap<char, HashSet<Pos<N>>>, AntinodeError> {
+        let unbounded = match rule {
+            AntinodeRule::Resonant => true,
+            AntinodeRule::Harmonics(n) => n > MAX_UNBOUNDED_HARMONICS,
+            AntinodeRule::Ratio { .. } => false,
+        };
+        if self.bounds.is_none() && unbounded {
+            return Err(AntinodeError::Unbounded(rule));
+        }
 
-    for (pos1, frequency) in map.iter().filter(|(_, &c)| c != '.') {
-        for (pos2, _) in map
+        self.antennas
             .iter()
-            .filter(|(pos2, &c)| c == *frequency && pos1 != *pos2)
-        {
-            // calculate direction vector
-            let dir = (pos2.0 - pos1.0, pos2.1 - pos1.1);
+            .map(|(&frequency, positions)| {
+                let mut anti_nodes = HashSet::new();
+                for (i, &pos1) in positions.iter().enumerate() {
+                    for &pos2 in &positions[i + 1..] {
+                        self.pair_antinodes(pos1, pos2, rule, &mut anti_nodes)?;
+                    }
+                }
+                Ok((frequency, anti_nodes))
+            })
+            .collect()
+    }
+
+    /// Distinct antinode locations across all frequencies.
+    fn unique_antinodes(&self, rule: AntinodeRule) -> Result<HashSet<Pos<N>>, AntinodeError> {
+        Ok(self.antinodes(rule)?.into_values().flatten().collect())
+    }
 
-            // reduce to smallest integer vector
-            let divisor = gcd(dir.0.abs(), dir.1.abs());
-            let dir = (dir.0 / divisor, dir.1 / divisor);
+    fn pair_antinodes(
+        &self,
+        pos1: Pos<N>,
+        pos2: Pos<N>,
+        rule: AntinodeRule,
+        anti_nodes: &mut HashSet<Pos<N>>,
+    ) -> Result<(), AntinodeError> {
+        let dir = sub(pos2, pos1);
 
-            let mut pos = *pos1;
+        match rule {
+            AntinodeRule::Ratio { far, near } => {
+                // an outside point P with |P - pos1| = far/near * |P - pos2|
+                // lies at pos2 + dir * near / (far - near), and symmetrically
+                // on the other side
+                let denominator = far - near;
+                if denominator == 0 {
+                    return Ok(());
+                }
+                let offset = scale(dir, near);
+                if offset.iter().any(|c| c % denominator != 0) {
+                    return Ok(());
+                }
+                let offset = offset.map(|c| c / denominator);
 
-            while map.contains_key(&pos) {
-                anti_nodes.insert(pos);
-                pos = (pos.0 + dir.0, pos.1 + dir.1);
+                for anti_node in [add(pos2, offset), sub(pos1, offset)] {
+                    if self.contains(anti_node) {
+                        anti_nodes.insert(anti_node);
+                    }
+                }
             }
+            AntinodeRule::Resonant | AntinodeRule::Harmonics(_) => {
+                // reduce to smallest integer vector
+                let divisor = dir.iter().fold(0, |divisor, c| gcd(divisor, c.abs()));
+                let step = dir.map(|c| c / divisor);
+                let max_harmonics = match rule {
+                    AntinodeRule::Harmonics(n) => n,
+                    _ => usize::MAX,
+                };
 
-            let mut pos = (pos1.0 - dir.0, pos1.1 - dir.1);
+                // everything between the two antennas
+                for k in 0..=divisor {
+                    anti_nodes.insert(add(pos1, scale(step, k)));
+                }
 
-            while map.contains_key(&pos) {
-                anti_nodes.insert(pos);
-                pos = (pos.0 - dir.0, pos.1 - dir.1);
+                // and outwards from each of them
+                for (start, step) in [(pos2, step), (pos1, scale(step, -1))] {
+                    let mut pos = start;
+                    for _ in 0..max_harmonics {
+                        pos = checked_add(pos, step).ok_or(AntinodeError::OutOfRange)?;
+                        if !self.contains(pos) {
+                            break;
+                        }
+                        anti_nodes.insert(pos);
+                    }
+                }
             }
         }
+        Ok(())
+    }
+}
+
+fn parse_coords<const N: usize>(parts: &[&str]) -> Result<Pos<N>, String> {
+    if parts.len() != N {
+        return Err(format!("Expected {} coordinates, found {}", N, parts.len()));
     }
 
-    println!("Part 2: {}", anti_nodes.len());
+    let mut pos = [0; N];
+    for (c, part) in pos.iter_mut().zip(parts) {
+        *c = part
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid coordinate {:?}", part.trim()))?;
+    }
+    Ok(pos)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let input = fs::read_to_string("input.txt").expect("Unable to read file");
+    let map = AntennaMap::from_input(&input);
+
+    let part1 = AntinodeRule::Ratio { far: 2, near: 1 };
+    println!("Part 1: {}", map.unique_antinodes(part1)?.len());
+    println!(
+        "Part 2: {}",
+        map.unique_antinodes(AntinodeRule::Resonant)?.len()
+    );
+
+    println!(
+        "First harmonic only: {}",
+        map.unique_antinodes(AntinodeRule::Harmonics(1))?.len()
+    );
+
+    println!("Antinodes per frequency:");
+    let mut by_frequency: Vec<_> = map.antinodes(AntinodeRule::Resonant)?.into_iter().collect();
+    by_frequency.sort_by_key(|(frequency, _)| *frequency);
+    for (frequency, anti_nodes) in by_frequency {
+        println!("  {}: {}", frequency, anti_nodes.len());
+    }
+
+    let map = map.unbounded();
+    println!("Part 1 unbounded: {}", map.unique_antinodes(part1)?.len());
+
+    // optionally also evaluate a 3D antenna field given as `x,y,z,frequency` lines
+    if let Some(path) = env::args().nth(1) {
+        let input = fs::read_to_string(path).expect("Unable to read file");
+        let field = AntennaMap::<3>::from_points(&input)?;
+
+        println!(
+            "3D ratio antinodes: {}",
+            field.unique_antinodes(part1)?.len()
+        );
+        if field.bounds.is_some() {
+            println!(
+                "3D resonant antinodes: {}",
+                field.unique_antinodes(AntinodeRule::Resonant)?.len()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_test_input() -> AntennaMap<2> {
+        let input = fs::read_to_string("test.txt").expect("Unable to read test file");
+        AntennaMap::from_input(&input)
+    }
+
+    #[test]
+    fn test_part1() {
+        let map = read_test_input();
+
+        assert_eq!(
+            map.unique_antinodes(AntinodeRule::Ratio { far: 2, near: 1 })
+                .unwrap()
+                .len(),
+            14
+        );
+    }
+
+    #[test]
+    fn test_part2() {
+        let map = read_test_input();
+
+        assert_eq!(
+            map.unique_antinodes(AntinodeRule::Resonant).unwrap().len(),
+            34
+        );
+        assert_eq!(
+            map.unique_antinodes(AntinodeRule::Harmonics(usize::MAX))
+                .unwrap()
+                .len(),
+            34
+        );
+    }
+
+    #[test]
+    fn test_by_frequency() {
+        let map = read_test_input();
+        let anti_nodes = map
+            .antinodes(AntinodeRule::Ratio { far: 2, near: 1 })
+            .unwrap();
+
+        assert_eq!(anti_nodes.len(), 2);
+        assert_eq!(anti_nodes[&'A'].len(), 5);
+        assert_eq!(anti_nodes[&'0'].len(), 10);
+    }
+
+    #[test]
+    fn test_unbounded() {
+        let map =
+            AntennaMap::from_input("..........\n...a......\n..........\n....a.....").unbounded();
+
+        let anti_nodes = map
+            .unique_antinodes(AntinodeRule::Ratio { far: 2, near: 1 })
+            .unwrap();
+        assert_eq!(anti_nodes, HashSet::from([[2, -1], [5, 5]]));
+
+        let anti_nodes = map.unique_antinodes(AntinodeRule::Harmonics(1)).unwrap();
+        assert_eq!(anti_nodes, HashSet::from([[2, -1], [3, 1], [4, 3], [5, 5]]));
+    }
+
+    #[test]
+    fn test_unbounded_resonant() {
+        let map = read_test_input().unbounded();
+
+        for rule in [
+            AntinodeRule::Resonant,
+            AntinodeRule::Harmonics(MAX_UNBOUNDED_HARMONICS + 1),
+            AntinodeRule::Harmonics(usize::MAX),
+        ] {
+            assert_eq!(map.antinodes(rule), Err(AntinodeError::Unbounded(rule)));
+        }
+        assert!(map
+            .antinodes(AntinodeRule::Harmonics(MAX_UNBOUNDED_HARMONICS))
+            .is_ok());
+
+        // harmonics that would leave the coordinate range
+        let field = AntennaMap::<2>::from_points(&format!("0,0,a\n{},1,a", i32::MAX / 2)).unwrap();
+        assert_eq!(
+            field.antinodes(AntinodeRule::Harmonics(2)),
+            Err(AntinodeError::OutOfRange)
+        );
+    }
+
+    #[test]
+    fn test_points_match_grid() {
+        let grid = read_test_input();
+        let points: String = grid
+            .antennas
+            .iter()
+            .flat_map(|(frequency, positions)| {
+                positions
+                    .iter()
+                    .map(move |[x, y]| format!("{},{},{}\n", x, y, frequency))
+            })
+            .collect();
+        let map = AntennaMap::<2>::from_points(&format!("bounds: 12,12\n{}", points)).unwrap();
+
+        assert_eq!(
+            map.unique_antinodes(AntinodeRule::Ratio { far: 2, near: 1 })
+                .unwrap(),
+            grid.unique_antinodes(AntinodeRule::Ratio { far: 2, near: 1 })
+                .unwrap()
+        );
+        assert_eq!(
+            map.unique_antinodes(AntinodeRule::Resonant).unwrap(),
+            grid.unique_antinodes(AntinodeRule::Resonant).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_points_errors() {
+        let error = |input: &str| {
+            AntennaMap::<3>::from_points(input)
+                .err()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(
+            error("1,1,1,a\n1,1,1,a"),
+            "line 2: Duplicate antenna a at [1, 1, 1]"
+        );
+        assert_eq!(
+            error("1,1,1,a\nbounds: 5,5,5"),
+            "line 2: Bounds must be on the first line"
+        );
+        assert_eq!(
+            error("1,1,1,1,a"),
+            "line 1: Expected 3 coordinates, found 4"
+        );
+        assert_eq!(error("1,1,a"), "line 1: Expected 3 coordinates, found 2");
+        assert_eq!(
+            error("bounds: 5,5"),
+            "line 1: Expected 3 coordinates, found 2"
+        );
+        assert_eq!(error("1,x,1,a"), "line 1: Invalid coordinate \"x\"");
+        assert_eq!(error("1,1,1, "), "line 1: Missing frequency");
+    }
+
+    #[test]
+    fn test_3d() {
+        let field =
+            AntennaMap::<3>::from_points("bounds: 7,7,7\n1,1,1,a\n3,2,3,a\n0,0,0,b").unwrap();
+
+        let anti_nodes = field
+            .unique_antinodes(AntinodeRule::Ratio { far: 2, near: 1 })
+            .unwrap();
+        assert_eq!(anti_nodes, HashSet::from([[5, 3, 5]]));
+
+        // step (2,1,2) is already reduced, so the line only hits every other z
+        let anti_nodes = field.unique_antinodes(AntinodeRule::Resonant).unwrap();
+        assert_eq!(anti_nodes, HashSet::from([[1, 1, 1], [3, 2, 3], [5, 3, 5]]));
+
+        let field = AntennaMap::<3>::from_points("0,0,0,a\n2,4,6,a").unwrap();
+        let anti_nodes = field.unique_antinodes(AntinodeRule::Harmonics(1)).unwrap();
+        assert_eq!(
+            anti_nodes,
+            HashSet::from([[-1, -2, -3], [0, 0, 0], [1, 2, 3], [2, 4, 6], [3, 6, 9]])
+        );
+    }
 }
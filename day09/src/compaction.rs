@@ -0,0 +1,379 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, BinaryHeap},
+};
+
+use crate::{DiskMap, File, Span, MAX_SPAN};
+
+/// A way of rearranging the files on a disk.
+pub trait CompactionStrategy {
+    /// Compacts `disk` in place and returns the number of moves made.
+    fn compact(&self, disk: &mut DiskMap) -> usize;
+}
+
+/// Moves single blocks from the end of the disk into the leftmost free blocks,
+/// splitting files where needed (part 1).
+pub struct FragmentBlocks;
+
+/// Moves each whole file, highest id first, into the leftmost gap that fits (part 2).
+pub struct FirstFit;
+
+/// Like `FirstFit`, but picks the smallest fitting gap, leftmost on ties.
+pub struct BestFit;
+
+/// Like `FirstFit`, but picks the largest fitting gap, leftmost on ties. Gaps
+/// of `MAX_SPAN` blocks or more count as equally large, as `GapIndex` keeps
+/// them together.
+pub struct WorstFit;
+
+/// Packs all files into one contiguous run at the start of the disk, without
+/// splitting them and in any order, with the fewest moves.
+///
+/// Only files that already lie completely inside the final run can stay, and
+/// the others must fill the holes those leave exactly. A greedy packing gives
+/// the first bound: keep all such files, place the others largest first into
+/// the smallest hole they fit, and evict the rightmost staying file until that
+/// works. A branch and bound search over which files stay, trying every way
+/// to fill the holes, then looks for fewer moves. The search is exponential,
+/// as this is a bin packing problem, so `limit` can cap its steps, after which
+/// the best packing found so far is kept.
+pub struct Defragment {
+    /// Search steps before settling for the best packing found, or `None` to
+    /// search until the fewest moves are proven
+    pub limit: Option<usize>,
+}
+
+impl CompactionStrategy for FragmentBlocks {
+    fn compact(&self, disk: &mut DiskMap) -> usize {
+        let mut files = std::mem::take(&mut disk.files);
+        let mut moved = vec![];
+
+        for gap in &disk.gaps {
+            let mut gap = *gap;
+
+            while gap.len > 0 {
+                let Some(last) = files.last_mut() else { break };
+                if last.span.start < gap.start {
+                    break;
+                }
+
+                let len = gap.len.min(last.span.len);
+                moved.push(File {
+                    id: last.id,
+                    span: Span {
+                        start: gap.start,
+                        len,
+                    },
+                });
+
+                last.span.len -= len;
+                if last.span.len == 0 {
+                    files.pop();
+                }
+                gap.start += len;
+                gap.len -= len;
+            }
+        }
+
+        let moves = moved.len();
+        files.extend(moved);
+        disk.files = files;
+        disk.normalize();
+        moves
+    }
+}
+
+impl CompactionStrategy for FirstFit {
+    fn compact(&self, disk: &mut DiskMap) -> usize {
+        move_whole_files(disk, |gaps, len, limit| {
+            (len..=MAX_SPAN)
+                .filter_map(|size| gaps.leftmost(size, limit).map(|start| (start, size)))
+                .min()
+                .map(|(_, size)| size)
+        })
+    }
+}
+
+impl CompactionStrategy for BestFit {
+    fn compact(&self, disk: &mut DiskMap) -> usize {
+        move_whole_files(disk, |gaps, len, limit| {
+            (len..=MAX_SPAN).find(|&size| gaps.leftmost(size, limit).is_some())
+        })
+    }
+}
+
+impl CompactionStrategy for WorstFit {
+    fn compact(&self, disk: &mut DiskMap) -> usize {
+        move_whole_files(disk, |gaps, len, limit| {
+            (len..=MAX_SPAN)
+                .rev()
+                .find(|&size| gaps.leftmost(size, limit).is_some())
+        })
+    }
+}
+
+impl CompactionStrategy for Defragment {
+    fn compact(&self, disk: &mut DiskMap) -> usize {
+        let total: u64 = disk.files.iter().map(|file| file.span.len).sum();
+
+        // indices of non-empty files inside the final run, left to right
+        let inside: Vec<usize> = (0..disk.files.len())
+            .filter(|&i| {
+                let span = disk.files[i].span;
+                span.len > 0 && span.start + span.len <= total
+            })
+            .collect();
+
+        let mut staying = inside.clone();
+        let greedy = loop {
+            if let Some(placement) = pack(&disk.files, &staying, total) {
+                break placement;
+            }
+            staying.pop();
+        };
+
+        let mut search = Search {
+            files: &disk.files,
+            inside: &inside,
+            total,
+            forced: disk.files.iter().filter(|file| file.span.len > 0).count() - inside.len(),
+            best: greedy,
+            steps: self.limit.unwrap_or(usize::MAX),
+        };
+        search.branch(&mut Vec::new(), 0);
+
+        let placement = search.best;
+        let moves = placement.len();
+        for (i, start) in placement {
+            disk.files[i].span.start = start;
+        }
+        disk.normalize();
+        moves
+    }
+}
+
+/// Gaps as one min-heap of `(start, len)` per size. Gaps longer than
+/// `MAX_SPAN` share the `MAX_SPAN` heap, as they fit any file that does.
+struct GapIndex {
+    heaps: Vec<BinaryHeap<Reverse<(u64, u64)>>>,
+}
+
+impl GapIndex {
+    fn new(gaps: &[Span]) -> Self {
+        let mut index = GapIndex {
+            heaps: vec![BinaryHeap::new(); MAX_SPAN as usize + 1],
+        };
+        for gap in gaps {
+            index.insert(gap.start, gap.len);
+        }
+        index
+    }
+
+    fn insert(&mut self, start: u64, len: u64) {
+        self.heaps[len.min(MAX_SPAN) as usize].push(Reverse((start, len)));
+    }
+
+    /// Start of the leftmost gap of `size` blocks, or at least `MAX_SPAN`
+    /// blocks, if it lies before `limit`.
+    fn leftmost(&self, size: u64, limit: u64) -> Option<u64> {
+        self.heaps[size as usize]
+            .peek()
+            .map(|&Reverse((start, _))| start)
+            .filter(|&start| start < limit)
+    }
+
+    /// Fills the start of the leftmost gap of `size` blocks with `len` blocks,
+    /// returning where they go.
+    fn take(&mut self, size: u64, len: u64) -> u64 {
+        let Reverse((start, gap_len)) = self.heaps[size as usize]
+            .pop()
+            .expect("No gap of that size");
+        if gap_len > len {
+            self.insert(start + len, gap_len - len);
+        }
+        start
+    }
+}
+
+/// Moves whole files, highest id first, into the gap size chosen by `pick`.
+///
+/// `pick` receives the gaps, the file length and the file start, and returns
+/// the size of a gap with a head before the file. Space freed by a moved file
+/// is never reused: every file processed later has a lower id and therefore
+/// lies to the left of it.
+fn move_whole_files(
+    disk: &mut DiskMap,
+    pick: impl Fn(&GapIndex, u64, u64) -> Option<u64>,
+) -> usize {
+    let mut gaps = GapIndex::new(&disk.gaps);
+    let mut moves = 0;
+
+    disk.files.sort_by_key(|file| Reverse(file.id));
+
+    for file in disk.files.iter_mut() {
+        let len = file.span.len;
+        if len == 0 || len > MAX_SPAN {
+            continue;
+        }
+
+        if let Some(size) = pick(&gaps, len, file.span.start) {
+            file.span.start = gaps.take(size, len);
+            moves += 1;
+        }
+    }
+
+    disk.normalize();
+    moves
+}
+
+/// Tries to place every non-empty file not in `staying` into the holes that
+/// the staying files leave in `0..total`. Returns the new start per moved file.
+fn pack(files: &[File], staying: &[usize], total: u64) -> Option<Vec<(usize, u64)>> {
+    // holes ordered by (len, start) for best-fit lookups
+    let mut holes = BTreeSet::new();
+    let mut end = 0;
+    for &i in staying {
+        let span = files[i].span;
+        if span.start > end {
+            holes.insert((span.start - end, end));
+        }
+        end = span.start + span.len;
+    }
+    if total > end {
+        holes.insert((total - end, end));
+    }
+
+    let mut moving: Vec<usize> = (0..files.len())
+        .filter(|&i| files[i].span.len > 0 && staying.binary_search(&i).is_err())
+        .collect();
+    moving.sort_by_key(|&i| Reverse(files[i].span.len));
+
+    let mut placement = Vec::with_capacity(moving.len());
+    for i in moving {
+        let len = files[i].span.len;
+        let &(size, start) = holes.range((len, 0)..).next()?;
+
+        holes.remove(&(size, start));
+        if size > len {
+            holes.insert((size - len, start + len));
+        }
+        placement.push((i, start));
+    }
+
+    Some(placement)
+}
+
+/// Branch and bound over which files inside the final run stay in place.
+struct Search<'a> {
+    files: &'a [File],
+    /// Non-empty files inside the final run, left to right
+    inside: &'a [usize],
+    total: u64,
+    /// Number of non-empty files that must move whatever stays
+    forced: usize,
+    /// New start per moved file in the packing with the fewest moves so far
+    best: Vec<(usize, u64)>,
+    /// Steps left before settling for `best`
+    steps: usize,
+}
+
+impl Search<'_> {
+    /// Decides whether `inside[next]` stays, given the files kept before it.
+    fn branch(&mut self, staying: &mut Vec<usize>, next: usize) {
+        let evicted = next - staying.len();
+        if self.steps == 0 || self.forced + evicted >= self.best.len() {
+            return;
+        }
+        self.steps -= 1;
+
+        let Some(&i) = self.inside.get(next) else {
+            if let Some(placement) = self.fill(staying) {
+                self.best = placement;
+            }
+            return;
+        };
+
+        staying.push(i);
+        self.branch(staying, next + 1);
+        staying.pop();
+        self.branch(staying, next + 1);
+    }
+
+    /// Places every non-empty file not in `staying` into the holes that the
+    /// staying files leave, trying every way until the holes are filled.
+    fn fill(&mut self, staying: &[usize]) -> Option<Vec<(usize, u64)>> {
+        self.steps = self.steps.saturating_sub(self.files.len());
+
+        let mut holes = vec![];
+        let mut end = 0;
+        for &i in staying {
+            let span = self.files[i].span;
+            if span.start > end {
+                holes.push(Span {
+                    start: end,
+                    len: span.start - end,
+                });
+            }
+            end = span.start + span.len;
+        }
+        if self.total > end {
+            holes.push(Span {
+                start: end,
+                len: self.total - end,
+            });
+        }
+
+        let mut moving: Vec<usize> = (0..self.files.len())
+            .filter(|&i| self.files[i].span.len > 0 && staying.binary_search(&i).is_err())
+            .collect();
+        // each hole takes at least one file
+        if moving.len() < holes.len() {
+            return None;
+        }
+        moving.sort_by_key(|&i| Reverse(self.files[i].span.len));
+
+        let mut placement = Vec::with_capacity(moving.len());
+        self.place(&moving, &mut holes, &mut placement)
+            .then_some(placement)
+    }
+
+    /// Places `moving` into `holes`, largest first. As the lengths add up to
+    /// the free space, placing them all fills every hole.
+    fn place(
+        &mut self,
+        moving: &[usize],
+        holes: &mut [Span],
+        placement: &mut Vec<(usize, u64)>,
+    ) -> bool {
+        let Some((&i, rest)) = moving.split_first() else {
+            return true;
+        };
+        let len = self.files[i].span.len;
+
+        // holes with the same length left are interchangeable
+        let mut tried = vec![];
+        for h in 0..holes.len() {
+            let hole = holes[h];
+            if hole.len < len || tried.contains(&hole.len) {
+                continue;
+            }
+            if self.steps == 0 {
+                return false;
+            }
+            self.steps -= 1;
+            tried.push(hole.len);
+
+            holes[h] = Span {
+                start: hole.start + len,
+                len: hole.len - len,
+            };
+            placement.push((i, hole.start));
+            if self.place(rest, holes, placement) {
+                return true;
+            }
+            placement.pop();
+            holes[h] = hole;
+        }
+        false
+    }
+}
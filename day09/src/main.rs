@@ -1,101 +1,484 @@
-use std::fs;
+use std::{
+    env, fs,
+    io::{BufWriter, Write},
+    time::Instant,
+};
 
-const EMPTY: u64 = u64::MAX;
+use compaction::{BestFit, CompactionStrategy, Defragment, FirstFit, FragmentBlocks, WorstFit};
 
-fn main() {
-    let input = fs::read_to_string("input2.txt").expect("Unable to read file");
-    let mut disk = vec![];
-    let max_id = (input.len() - 1) as u64 / 2;
+mod compaction;
+
+/// Largest span a single digit of the dense format can describe.
+const MAX_SPAN: u64 = 9;
+
+/// A contiguous run of blocks. Files carry their id, free space does not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Span {
+    start: u64,
+    len: u64,
+}
 
-    for (i, c) in input.chars().enumerate() {
-        let size = c.to_digit(10).unwrap();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct File {
+    id: u64,
+    span: Span,
+}
+
+/// Disk layout stored as spans rather than one entry per block.
+///
+/// `files` are sorted by position and may hold several fragments of the same
+/// id after block compaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DiskMap {
+    files: Vec<File>,
+    gaps: Vec<Span>,
+    /// Total number of blocks, including trailing free space.
+    len: u64,
+}
+
+impl DiskMap {
+    /// Parses the dense format, where digits alternate between file and gap sizes.
+    fn parse(input: &str) -> Self {
+        let mut files = vec![];
+        let mut gaps = vec![];
+        let mut start = 0;
+
+        for (i, c) in input.trim().chars().enumerate() {
+            let len = c.to_digit(10).expect("Invalid digit in disk map") as u64;
+            let span = Span { start, len };
 
-        for _ in 0..size {
             if i % 2 == 0 {
-                disk.push((i as u64) / 2);
-            } else {
-                disk.push(EMPTY);
+                files.push(File {
+                    id: (i as u64) / 2,
+                    span,
+                });
+            } else if len > 0 {
+                gaps.push(span);
             }
+            start += len;
+        }
+
+        DiskMap {
+            files,
+            gaps,
+            len: start,
         }
     }
 
-    let initial_disk = disk.clone();
+    /// Encodes the layout back into the dense digit format.
+    ///
+    /// File ids are implied by position in that format, so `ids` returns the
+    /// id of each non-empty file in the same order. Gaps longer than nine
+    /// blocks are split up by zero-length files, and free space at the start
+    /// of the disk follows a zero-length first file.
+    fn encode(&self) -> String {
+        let mut dense = String::new();
+        let mut end = 0;
 
-    // println!("{:?}", disk);
+        for file in self.files.iter().filter(|file| file.span.len > 0) {
+            assert!(file.span.len <= MAX_SPAN, "File too large for dense format");
+            if !dense.is_empty() {
+                encode_gap(&mut dense, file.span.start - end);
+            } else if file.span.start > 0 {
+                dense.push('0');
+                encode_gap(&mut dense, file.span.start);
+            }
+            dense.push(char::from_digit(file.span.len as u32, 10).unwrap());
+            end = file.span.start + file.span.len;
+        }
+        if self.len > end {
+            if dense.is_empty() {
+                dense.push('0');
+            }
+            encode_gap(&mut dense, self.len - end);
+        }
 
-    loop {
-        let last_file_pos: usize = disk.iter().rposition(|n| *n != EMPTY).unwrap();
+        dense
+    }
 
-        let first_empty_pos = disk.iter().position(|n| *n == EMPTY).unwrap();
+    /// Ids of the non-empty files in disk order, matching `encode`.
+    fn ids(&self) -> Vec<u64> {
+        self.files
+            .iter()
+            .filter(|file| file.span.len > 0)
+            .map(|file| file.id)
+            .collect()
+    }
 
-        if first_empty_pos < last_file_pos {
-            disk[first_empty_pos] = disk[last_file_pos];
-            disk[last_file_pos] = EMPTY;
-        } else {
-            break;
+    /// Restores the invariants after files have been moved around.
+    fn normalize(&mut self) {
+        self.files.sort_by_key(|file| file.span.start);
+        self.gaps = self.free_spans();
+    }
+
+    /// Recomputes the free spans between files, which must be sorted by start.
+    fn free_spans(&self) -> Vec<Span> {
+        let mut gaps = vec![];
+        let mut end = 0;
+
+        for file in &self.files {
+            if file.span.start > end {
+                gaps.push(Span {
+                    start: end,
+                    len: file.span.start - end,
+                });
+            }
+            end = end.max(file.span.start + file.span.len);
+        }
+        if self.len > end {
+            gaps.push(Span {
+                start: end,
+                len: self.len - end,
+            });
         }
+
+        gaps
     }
-    // println!("{:?}", disk);
 
-    let sum: u64 = disk
-        .iter()
-        .enumerate()
-        .filter_map(|(i, &c)| match c {
-            EMPTY => None,
-            _ => Some((i as u64) * c),
-        })
-        .sum();
+    fn checksum(&self) -> u64 {
+        self.files
+            .iter()
+            .map(|file| {
+                let Span { start, len } = file.span;
+                // sum of positions start..start + len
+                file.id * (len * start + len * len.saturating_sub(1) / 2)
+            })
+            .sum()
+    }
+}
 
-    println!("Part 1: {}", sum);
+/// Appends a gap, padding with zero-length files where it exceeds one digit.
+fn encode_gap(dense: &mut String, mut len: u64) {
+    while len > MAX_SPAN {
+        dense.push_str("90");
+        len -= MAX_SPAN;
+    }
+    dense.push(char::from_digit(len as u32, 10).unwrap());
+}
 
-    let mut disk = initial_disk.clone();
+fn main() {
+    let input = fs::read_to_string("input.txt").expect("Unable to read file");
+    let initial_disk = DiskMap::parse(&input);
 
-    for id in (0..=max_id).rev() {
-        let file_pos: usize = disk.iter().position(|n| *n == id).unwrap();
+    let strategies: [(&str, &dyn CompactionStrategy); 5] = [
+        ("Part 1", &FragmentBlocks),
+        ("Part 2", &FirstFit),
+        ("Best fit", &BestFit),
+        ("Worst fit", &WorstFit),
+        (
+            "Defragment",
+            &Defragment {
+                limit: Some(1_000_000),
+            },
+        ),
+    ];
 
-        let mut size = 1;
+    for (name, strategy) in strategies {
+        let start = Instant::now();
+        let mut disk = initial_disk.clone();
+        let moves = strategy.compact(&mut disk);
+        let duration = start.elapsed();
 
-        while disk.get(file_pos + size) == Some(&id) {
-            size += 1;
+        println!(
+            "{name}: {}, {moves} moves, took {duration:?}",
+            disk.checksum()
+        );
+
+        // Optionally write the layout as <prefix>-<strategy>.txt, the dense
+        // format followed by the file ids in disk order
+        if let Some(prefix) = env::args().nth(1) {
+            let path = format!("{prefix}-{}.txt", name.to_lowercase().replace(' ', "-"));
+            let file = fs::File::create(&path).expect("Failed to create output file");
+            let mut out = BufWriter::new(file);
+
+            let ids: Vec<String> = disk.ids().iter().map(u64::to_string).collect();
+            writeln!(out, "{}\n{}", disk.encode(), ids.join(","))
+                .and_then(|_| out.flush())
+                .expect("Failed to write layout");
         }
+    }
+}
 
-        // find leftmost empty spot which is large enough
-        let mut empty_pos = disk.iter().position(|n| *n == EMPTY).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Reverse;
 
-        loop {
-            let mut gap_size = 1;
+    fn read_test_input() -> DiskMap {
+        let input = fs::read_to_string("test.txt").expect("Unable to read test file");
+        DiskMap::parse(&input)
+    }
 
-            while disk.get(empty_pos + gap_size) == Some(&EMPTY) {
-                gap_size += 1;
-            }
+    /// Like `DiskMap::parse`, but assigns the given ids to the files in order
+    /// instead of numbering them by position. Zero-length files are dropped.
+    fn parse_with_ids(input: &str, ids: &[u64]) -> DiskMap {
+        let mut disk = DiskMap::parse(input);
+        disk.files.retain(|file| file.span.len > 0);
+        assert_eq!(disk.files.len(), ids.len(), "Expected one id per file");
 
-            if empty_pos >= file_pos {
-                break;
-            }
+        for (file, &id) in disk.files.iter_mut().zip(ids) {
+            file.id = id;
+        }
+        disk
+    }
+
+    #[test]
+    fn test_part1() {
+        let mut disk = read_test_input();
+        FragmentBlocks.compact(&mut disk);
+
+        assert_eq!(disk.checksum(), 1928);
+    }
+
+    #[test]
+    fn test_part2() {
+        let mut disk = read_test_input();
+        FirstFit.compact(&mut disk);
+
+        assert_eq!(disk.checksum(), 2858);
+    }
 
-            if gap_size >= size {
-                for i in 0..size {
-                    disk[empty_pos + i] = id;
-                    disk[file_pos + i] = EMPTY;
+    #[test]
+    fn test_large_map() {
+        // 100k files with pseudo-random sizes
+        let mut seed: u64 = 42;
+        let input: String = (0..200_000)
+            .map(|i| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                let digit = (seed >> 33) % 10;
+                let digit = if i % 2 == 0 { digit.max(1) } else { digit };
+                char::from_digit(digit as u32, 10).unwrap()
+            })
+            .collect();
+
+        let initial_disk = DiskMap::parse(&input);
+        let mut disk = initial_disk.clone();
+        FirstFit.compact(&mut disk);
+
+        assert_eq!(disk.files.len(), 100_000);
+        for pair in disk.files.windows(2) {
+            assert!(pair[0].span.start + pair[0].span.len <= pair[1].span.start);
+        }
+        for file in &disk.files {
+            let original = initial_disk.files[file.id as usize];
+            assert_eq!(file.span.len, original.span.len);
+            assert!(file.span.start <= original.span.start);
+        }
+    }
+
+    #[test]
+    fn test_fit_strategies() {
+        // a 2-block file with a 3-block and a 2-block gap to its left
+        let disk = DiskMap::parse("13322");
+
+        // taking the 3-block gap leaves no room for the 3-block file
+        let mut first_fit = disk.clone();
+        assert_eq!(FirstFit.compact(&mut first_fit), 1);
+        assert_eq!(first_fit.encode(), "102134");
+        assert_eq!(first_fit.ids(), vec![0, 2, 1]);
+
+        let mut best_fit = disk.clone();
+        assert_eq!(BestFit.compact(&mut best_fit), 2);
+        assert_eq!(best_fit.encode(), "103322");
+        assert_eq!(best_fit.ids(), vec![0, 1, 2]);
+
+        let mut worst_fit = disk;
+        assert_eq!(WorstFit.compact(&mut worst_fit), 1);
+        assert_eq!(worst_fit, first_fit);
+    }
+
+    #[test]
+    fn test_long_gap() {
+        // free space left behind by moved files can exceed one digit
+        let file = |id, start, len| File {
+            id,
+            span: Span { start, len },
+        };
+        let mut disk = DiskMap {
+            files: vec![file(0, 0, 1), file(1, 19, 2)],
+            gaps: vec![],
+            len: 21,
+        };
+        disk.normalize();
+        assert_eq!(disk.gaps, vec![Span { start: 1, len: 18 }]);
+
+        assert_eq!(FirstFit.compact(&mut disk), 1);
+        assert_eq!(disk.files[1].span, Span { start: 1, len: 2 });
+    }
+
+    #[test]
+    fn test_defragment() {
+        let mut disk = read_test_input();
+        let total: u64 = disk.files.iter().map(|file| file.span.len).sum();
+
+        let moves = Defragment { limit: None }.compact(&mut disk);
+
+        assert!(disk.gaps.iter().all(|gap| gap.start >= total));
+        assert_eq!(
+            disk.files.iter().filter(|file| file.span.len > 0).count(),
+            10
+        );
+        // files 6 to 9 lie beyond the first 28 blocks, and two more files
+        // have to make room for them; the greedy packing evicts three
+        assert_eq!(moves, 6);
+    }
+
+    /// Fewest moves that pack all files into one run at the start, by trying
+    /// every set of files to keep in place and every way to place the rest.
+    fn fewest_moves(disk: &DiskMap) -> usize {
+        fn fits(lens: &[u64], holes: &mut [u64]) -> bool {
+            let Some((&len, rest)) = lens.split_first() else {
+                return true;
+            };
+            (0..holes.len()).any(|i| {
+                if holes[i] < len {
+                    return false;
+                }
+                holes[i] -= len;
+                let fits = fits(rest, holes);
+                holes[i] += len;
+                fits
+            })
+        }
+
+        let files: Vec<Span> = disk
+            .files
+            .iter()
+            .map(|file| file.span)
+            .filter(|span| span.len > 0)
+            .collect();
+        let total: u64 = files.iter().map(|span| span.len).sum();
+
+        (0..1u32 << files.len())
+            .filter(|&staying| {
+                let stays = |i: usize| staying & (1 << i) != 0;
+                if (0..files.len()).any(|i| stays(i) && files[i].start + files[i].len > total) {
+                    return false;
                 }
-                break;
-            } else {
-                empty_pos += gap_size;
-                while disk.get(empty_pos) != Some(&EMPTY) {
-                    empty_pos += 1;
+
+                let mut holes = vec![];
+                let mut end = 0;
+                for span in (0..files.len()).filter(|&i| stays(i)).map(|i| files[i]) {
+                    holes.push(span.start - end);
+                    end = span.start + span.len;
                 }
-            }
+                holes.push(total - end);
+
+                let mut moving: Vec<u64> = (0..files.len())
+                    .filter(|&i| !stays(i))
+                    .map(|i| files[i].len)
+                    .collect();
+                moving.sort_by_key(|&len| Reverse(len));
+                fits(&moving, &mut holes)
+            })
+            .map(|staying| files.len() - staying.count_ones() as usize)
+            .min()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_defragment_against_brute_force() {
+        let mut seed: u64 = 7;
+        let mut digit = |max: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) % (max + 1)
+        };
+
+        for _ in 0..300 {
+            let input: String = (0..13)
+                .map(|i| {
+                    if i % 2 == 0 {
+                        digit(4).max(1)
+                    } else {
+                        digit(3)
+                    }
+                })
+                .map(|digit| char::from_digit(digit as u32, 10).unwrap())
+                .collect();
+            let mut disk = DiskMap::parse(&input);
+            let optimum = fewest_moves(&disk);
+            let total: u64 = disk.files.iter().map(|file| file.span.len).sum();
+
+            let moves = Defragment { limit: None }.compact(&mut disk);
+            assert!(disk.gaps.iter().all(|gap| gap.start >= total), "{}", input);
+            assert_eq!(moves, optimum, "{}", input);
         }
+
+        let mut disk = read_test_input();
+        assert_eq!(fewest_moves(&disk), 6);
+        assert_eq!(Defragment { limit: None }.compact(&mut disk), 6);
     }
 
-    let sum: u64 = disk
-        .iter()
-        .enumerate()
-        .filter_map(|(i, &c)| match c {
-            EMPTY => None,
-            _ => Some((i as u64) * c),
-        })
-        .sum();
+    #[test]
+    fn test_round_trip() {
+        let input = fs::read_to_string("test.txt").expect("Unable to read test file");
+        let disk = DiskMap::parse(&input);
+        assert_eq!(disk.encode(), input.trim());
+        assert_eq!(DiskMap::parse(&disk.encode()), disk);
 
-    println!("Part 2: {}", sum);
+        let strategies: [&dyn CompactionStrategy; 5] = [
+            &FragmentBlocks,
+            &FirstFit,
+            &BestFit,
+            &WorstFit,
+            &Defragment { limit: None },
+        ];
+        for strategy in strategies {
+            let mut disk = disk.clone();
+            strategy.compact(&mut disk);
+
+            let decoded = parse_with_ids(&disk.encode(), &disk.ids());
+            assert_eq!(decoded.checksum(), disk.checksum());
+            assert_eq!(decoded.len, disk.len);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_after_compaction() {
+        // the dense encoding plus the id order must describe the same disk
+        let mut seed: u64 = 3;
+        let input: String = (0..2_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                char::from_digit(((seed >> 33) % 10) as u32, 10).unwrap()
+            })
+            .collect();
+        let disk = DiskMap::parse(&input);
+
+        let strategies: [&dyn CompactionStrategy; 5] = [
+            &FragmentBlocks,
+            &FirstFit,
+            &BestFit,
+            &WorstFit,
+            &Defragment {
+                limit: Some(100_000),
+            },
+        ];
+        for strategy in strategies {
+            let mut disk = disk.clone();
+            strategy.compact(&mut disk);
+
+            let decoded = parse_with_ids(&disk.encode(), &disk.ids());
+            assert_eq!(decoded.checksum(), disk.checksum());
+            assert_eq!(decoded.len, disk.len);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_leading_gap() {
+        let disk = DiskMap::parse("0312");
+        assert_eq!(disk.encode(), "0312");
+        assert_eq!(DiskMap::parse(&disk.encode()).checksum(), disk.checksum());
+
+        let disk = DiskMap::parse("0902");
+        assert_eq!(disk.encode(), "0902");
+        assert_eq!(DiskMap::parse(&disk.encode()), disk);
+
+        // no files at all, only free space
+        let disk = DiskMap::parse("0509");
+        assert_eq!(disk.encode(), "0905");
+        let decoded = DiskMap::parse(&disk.encode());
+        assert!(decoded.files.iter().all(|file| file.span.len == 0));
+        assert_eq!(decoded.len, 14);
+    }
 }
@@ -1,42 +1,137 @@
 use std::{
     collections::{HashMap, HashSet, VecDeque},
-    fs,
+    env,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    time::Instant,
 };
 
+type Pos = (i32, i32);
+
 // Directions for movement: right, down, left, up
 const DIRS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
 
+/// Which trails count: the heights they start and end at, and how much a
+/// single step may change the height.
+///
+/// Trails ascend if `start < end` and descend otherwise, so every step has to
+/// move towards `end` and trails can never loop.
+#[derive(Debug, Clone, Copy)]
+struct ClimbRules {
+    start: u32,
+    end: u32,
+    min_step: u32,
+    max_step: u32,
+}
+
+impl ClimbRules {
+    /// The puzzle rules: from 0 to 9, one step up at a time
+    const PUZZLE: ClimbRules = ClimbRules {
+        start: 0,
+        end: 9,
+        min_step: 1,
+        max_step: 1,
+    };
+
+    /// From 0 to 9, climbing by anything from 1 up to `k` per step
+    fn ascend_up_to(k: u32) -> Self {
+        ClimbRules {
+            max_step: k,
+            ..Self::PUZZLE
+        }
+    }
+
+    /// From 9 down to 0, one step down at a time
+    fn descending() -> Self {
+        ClimbRules {
+            start: 9,
+            end: 0,
+            ..Self::PUZZLE
+        }
+    }
+
+    /// Whether a single step from `from` to `to` is allowed
+    fn allows(&self, from: u32, to: u32) -> bool {
+        let (low, high) = (self.start.min(self.end), self.start.max(self.end));
+        let step = if self.start <= self.end {
+            to.checked_sub(from)
+        } else {
+            from.checked_sub(to)
+        };
+
+        (low..=high).contains(&to)
+            && step.is_some_and(|step| (self.min_step..=self.max_step).contains(&step))
+    }
+}
+
 fn main() {
     // Read input from file and parse into a height map
     let input = fs::read_to_string("input.txt").expect("Failed to read input file");
     let map = parse_map(&input);
 
     // Calculate results for both parts
-    let (part_1, part_2) = calculate_trails(&map);
+    let start = Instant::now();
+    let (part_1, part_2) = calculate_trails(&map, ClimbRules::PUZZLE);
+    let duration = start.elapsed();
+
+    println!("Part 1: {}, Part 2: {}, took {duration:?}", part_1, part_2);
+
+    // Same results from the height-ordered DP
+    let start = Instant::now();
+    let grid = Grid::parse(&input);
+    let counts = count_trails(&grid, ClimbRules::PUZZLE);
+    let (part_1, part_2) = counts.totals(&grid, ClimbRules::PUZZLE);
+    let duration = start.elapsed();
+
+    println!(
+        "Part 1: {}, Part 2: {}, took {duration:?} (DP)",
+        part_1, part_2
+    );
+
+    for (name, rules) in [
+        ("Climbing up to 2", ClimbRules::ascend_up_to(2)),
+        ("Descending", ClimbRules::descending()),
+    ] {
+        let (score, rating) = count_trails(&grid, rules).totals(&grid, rules);
+        println!("{name}: {score}, {rating}");
+    }
+
+    // Optionally stream every trail to the given file
+    if let Some(path) = env::args().nth(1) {
+        let file = File::create(&path).expect("Failed to create output file");
+        let mut out = BufWriter::new(file);
+
+        let mut written = 0;
+        for trailhead in grid.trailheads(ClimbRules::PUZZLE) {
+            written += write_trails(&grid, ClimbRules::PUZZLE, &counts, trailhead, &mut out)
+                .expect("Failed to write trails");
+        }
+        out.flush().expect("Failed to write trails");
 
-    println!("Part 1: {}, Part 2: {}", part_1, part_2);
+        println!("Wrote {written} trails to {path}");
+    }
 }
 
-/// Parses the input string into a height map
-fn parse_map(input: &str) -> HashMap<(i32, i32), u32> {
+/// Parses the input string into a height map, leaving out impassable cells
+fn parse_map(input: &str) -> HashMap<Pos, u32> {
     input
         .lines()
         .enumerate()
         .flat_map(|(y, line)| {
-            line.chars().enumerate().map(move |(x, c)| {
-                ((x as i32, y as i32), c.to_digit(10).expect("Invalid digit in map"))
+            line.chars().enumerate().filter_map(move |(x, c)| {
+                c.to_digit(10).map(|height| ((x as i32, y as i32), height))
             })
         })
         .collect()
 }
 
 /// Calculates the number of trail ends and total trails
-fn calculate_trails(map: &HashMap<(i32, i32), u32>) -> (usize, usize) {
+fn calculate_trails(map: &HashMap<Pos, u32>, rules: ClimbRules) -> (usize, usize) {
     let mut trail_end_count = 0;
     let mut trail_count = 0;
 
-    for (&trailhead, _) in map.iter().filter(|(_, &height)| height == 0) {
-        let (trail_ends, trails) = explore_trails(map, trailhead);
+    for (&trailhead, _) in map.iter().filter(|(_, &height)| height == rules.start) {
+        let (trail_ends, trails) = explore_trails(map, trailhead, rules);
         trail_end_count += trail_ends.len();
         trail_count += trails.len();
     }
@@ -46,9 +141,10 @@ fn calculate_trails(map: &HashMap<(i32, i32), u32>) -> (usize, usize) {
 
 /// Explores trails starting from a given trailhead
 fn explore_trails(
-    map: &HashMap<(i32, i32), u32>,
-    start: (i32, i32),
-) -> (HashSet<(i32, i32)>, HashSet<Vec<(i32, i32)>>) {
+    map: &HashMap<Pos, u32>,
+    start: Pos,
+    rules: ClimbRules,
+) -> (HashSet<Pos>, HashSet<Vec<Pos>>) {
     let mut to_visit = VecDeque::from([vec![start]]);
     let mut visited = HashSet::new();
     let mut trails = HashSet::new();
@@ -63,18 +159,18 @@ fn explore_trails(
         let (x, y) = *trail.last().expect("Trail should not be empty");
         let height = *map.get(&(x, y)).expect("Position missing in map");
 
-        if height == 9 {
+        if height == rules.end {
             // Mark the end of a trail
             trails.insert(trail.clone());
             trail_end_positions.insert((x, y));
             continue;
         }
 
-        // Add neighboring positions with valid height increments
+        // Add neighboring positions with valid height changes
         for &(dx, dy) in &DIRS {
             let new_pos = (x + dx, y + dy);
             if let Some(&new_height) = map.get(&new_pos) {
-                if new_height == height + 1 {
+                if rules.allows(height, new_height) {
                     let mut new_trail = trail.clone();
                     new_trail.push(new_pos);
                     to_visit.push_back(new_trail);
@@ -85,3 +181,254 @@ fn explore_trails(
 
     (trail_end_positions, trails)
 }
+
+/// Dense, row-major height map. Impassable cells have no height.
+struct Grid {
+    width: usize,
+    height: usize,
+    cells: Vec<Option<u32>>,
+}
+
+impl Grid {
+    fn parse(input: &str) -> Self {
+        let lines: Vec<&str> = input.lines().collect();
+        let width = lines.first().map_or(0, |line| line.len());
+        let cells = lines
+            .iter()
+            .flat_map(|line| line.chars())
+            .map(|c| c.to_digit(10))
+            .collect();
+
+        Grid {
+            width,
+            height: lines.len(),
+            cells,
+        }
+    }
+
+    fn position(&self, index: usize) -> Pos {
+        ((index % self.width) as i32, (index / self.width) as i32)
+    }
+
+    /// Indices of the orthogonal neighbours of a cell
+    fn neighbors(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        let (x, y) = self.position(index);
+
+        DIRS.iter().filter_map(move |&(dx, dy)| {
+            let (nx, ny) = (x + dx, y + dy);
+            if nx < 0 || ny < 0 || nx as usize >= self.width || ny as usize >= self.height {
+                None
+            } else {
+                Some(ny as usize * self.width + nx as usize)
+            }
+        })
+    }
+
+    /// Neighbours a trail may step to from a cell
+    fn steps(&self, index: usize, rules: ClimbRules) -> impl Iterator<Item = usize> + '_ {
+        let height = self.cells[index];
+
+        self.neighbors(index).filter(move |&next| {
+            matches!((height, self.cells[next]), (Some(from), Some(to)) if rules.allows(from, to))
+        })
+    }
+
+    fn trailheads(&self, rules: ClimbRules) -> impl Iterator<Item = usize> + '_ {
+        (0..self.cells.len()).filter(move |&i| self.cells[i] == Some(rules.start))
+    }
+}
+
+/// Per-cell results of the trail DP
+struct TrailCounts {
+    /// Number of distinct summits reachable from each cell
+    summits: Vec<usize>,
+    /// Number of distinct trails from each cell to any summit
+    trails: Vec<u64>,
+}
+
+impl TrailCounts {
+    /// Sums score and rating over all trailheads
+    fn totals(&self, grid: &Grid, rules: ClimbRules) -> (usize, u64) {
+        grid.trailheads(rules).fold((0, 0), |(score, rating), i| {
+            (score + self.summits[i], rating + self.trails[i])
+        })
+    }
+}
+
+/// Counts reachable summits and distinct trails for every cell.
+///
+/// Heights are processed from the trail end back towards its start, so every
+/// cell only depends on cells already done. Reachable summits are tracked as
+/// one bit mask per cell, for 64 summits per pass, which keeps memory linear
+/// in the number of cells however many summits there are.
+fn count_trails(grid: &Grid, rules: ClimbRules) -> TrailCounts {
+    let mut levels: Vec<Vec<usize>> = vec![vec![]; 10];
+    for (i, &height) in grid.cells.iter().enumerate() {
+        if let Some(height) = height {
+            levels[height as usize].push(i);
+        }
+    }
+
+    let order: Vec<u32> = if rules.start <= rules.end {
+        (rules.start..=rules.end).rev().collect()
+    } else {
+        (rules.end..=rules.start).collect()
+    };
+    let (&end, below) = order.split_first().expect("Rules span at least one height");
+
+    let mut summits = vec![0; grid.cells.len()];
+    let mut trails = vec![0; grid.cells.len()];
+
+    for &cell in &levels[end as usize] {
+        summits[cell] = 1;
+        trails[cell] = 1;
+    }
+    for &height in below {
+        for &cell in &levels[height as usize] {
+            trails[cell] = grid.steps(cell, rules).map(|next| trails[next]).sum();
+        }
+    }
+
+    let mut reachable = vec![0u64; grid.cells.len()];
+    for batch in levels[end as usize].chunks(64) {
+        reachable.fill(0);
+        for (bit, &cell) in batch.iter().enumerate() {
+            reachable[cell] = 1 << bit;
+        }
+
+        for &height in below {
+            for &cell in &levels[height as usize] {
+                let mask = grid
+                    .steps(cell, rules)
+                    .fold(0, |mask, next| mask | reachable[next]);
+                reachable[cell] = mask;
+                summits[cell] += mask.count_ones() as usize;
+            }
+        }
+    }
+
+    TrailCounts { summits, trails }
+}
+
+/// Writes every trail from `trailhead` to `out`, one per line as space-separated
+/// `x,y` coordinates, and returns how many were written.
+///
+/// Trails are walked depth-first and only through cells that still lead to a
+/// summit according to `counts`, so only the current trail is held in memory.
+fn write_trails(
+    grid: &Grid,
+    rules: ClimbRules,
+    counts: &TrailCounts,
+    trailhead: usize,
+    out: &mut impl Write,
+) -> io::Result<u64> {
+    fn walk(
+        grid: &Grid,
+        rules: ClimbRules,
+        counts: &TrailCounts,
+        trail: &mut Vec<usize>,
+        out: &mut impl Write,
+    ) -> io::Result<u64> {
+        let cell = *trail.last().expect("Trail should not be empty");
+
+        if grid.cells[cell] == Some(rules.end) {
+            let line: Vec<String> = trail
+                .iter()
+                .map(|&i| {
+                    let (x, y) = grid.position(i);
+                    format!("{x},{y}")
+                })
+                .collect();
+            writeln!(out, "{}", line.join(" "))?;
+            return Ok(1);
+        }
+
+        let mut written = 0;
+        for next in grid.steps(cell, rules) {
+            if counts.trails[next] > 0 {
+                trail.push(next);
+                written += walk(grid, rules, counts, trail, out)?;
+                trail.pop();
+            }
+        }
+        Ok(written)
+    }
+
+    if counts.trails[trailhead] == 0 {
+        return Ok(0);
+    }
+    walk(grid, rules, counts, &mut vec![trailhead], out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calculate_trails() {
+        let input = fs::read_to_string("test.txt").expect("Failed to read test file");
+        let map = parse_map(&input);
+
+        assert_eq!(calculate_trails(&map, ClimbRules::PUZZLE), (36, 81));
+    }
+
+    #[test]
+    fn test_count_trails() {
+        let input = fs::read_to_string("test.txt").expect("Failed to read test file");
+        let grid = Grid::parse(&input);
+        let counts = count_trails(&grid, ClimbRules::PUZZLE);
+
+        assert_eq!(counts.totals(&grid, ClimbRules::PUZZLE), (36, 81));
+        // trailhead at (2, 0) has score 5 and rating 20
+        assert_eq!(counts.summits[2], 5);
+        assert_eq!(counts.trails[2], 20);
+    }
+
+    #[test]
+    fn test_dp_matches_search() {
+        let input = fs::read_to_string("input.txt").expect("Failed to read input file");
+        let map = parse_map(&input);
+        let grid = Grid::parse(&input);
+
+        for rules in [
+            ClimbRules::PUZZLE,
+            ClimbRules::ascend_up_to(3),
+            ClimbRules::descending(),
+        ] {
+            let (score, rating) = calculate_trails(&map, rules);
+            let counts = count_trails(&grid, rules);
+            assert_eq!(counts.totals(&grid, rules), (score, rating as u64));
+        }
+    }
+
+    #[test]
+    fn test_impassable() {
+        let input = "..90..9\n...1.98\n...2..7\n6543456\n765.987\n876....\n987....";
+        let (score, _) = calculate_trails(&parse_map(input), ClimbRules::PUZZLE);
+        assert_eq!(score, 4);
+
+        let input = ".....0.\n..4321.\n..5..2.\n..6543.\n..7..4.\n..8765.\n..9....";
+        let grid = Grid::parse(input);
+        let counts = count_trails(&grid, ClimbRules::PUZZLE);
+        assert_eq!(counts.totals(&grid, ClimbRules::PUZZLE), (1, 3));
+    }
+
+    #[test]
+    fn test_write_trails() {
+        let input = ".....0.\n..4321.\n..5..2.\n..6543.\n..7..4.\n..8765.\n..9....";
+        let grid = Grid::parse(input);
+        let counts = count_trails(&grid, ClimbRules::PUZZLE);
+
+        let mut out = vec![];
+        let written = write_trails(&grid, ClimbRules::PUZZLE, &counts, 5, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert_eq!(written, 3);
+        assert_eq!(out.lines().count(), 3);
+        for line in out.lines() {
+            assert!(line.starts_with("5,0 "));
+            assert!(line.ends_with(" 2,6"));
+            assert_eq!(line.split(' ').count(), 10);
+        }
+    }
+}
@@ -1,36 +1,308 @@
-use cached::proc_macro::cached;
+use std::{collections::HashMap, env, error::Error, fmt, fs};
 
-fn main() {
-    let input = std::fs::read_to_string("input.txt").unwrap();
+use num::{BigUint, Zero};
+use transitions::TransitionGraph;
 
-    let numbers: Vec<u64> = input.split(" ").map(|s| s.parse().unwrap()).collect();
+mod transitions;
 
-    let part1 = numbers
-        .iter()
-        .fold(0, |count, &number| count + blink(number, 25));
+/// Which stones a rule applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Condition {
+    Equals(u64),
+    EvenDigits,
+    OddDigits,
+    Any,
+}
+
+/// What a rule turns a stone into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Replace(u64),
+    /// Split the digits into a left and a right half
+    Split,
+    Multiply(u64),
+    Add(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rule {
+    condition: Condition,
+    action: Action,
+}
+
+/// A stone whose next value does not fit into a `u64`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct StoneOverflow(u64);
+
+impl fmt::Display for StoneOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Stone {} outgrows 64 bits", self.0)
+    }
+}
+
+impl Error for StoneOverflow {}
+
+/// Ordered list of rules; the first one matching a stone is applied, and
+/// stones without a matching rule stay as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StoneRules {
+    rules: Vec<Rule>,
+}
+
+impl StoneRules {
+    /// 0 becomes 1, even digit counts split, everything else is multiplied by 2024
+    fn puzzle() -> Self {
+        StoneRules {
+            rules: vec![
+                Rule {
+                    condition: Condition::Equals(0),
+                    action: Action::Replace(1),
+                },
+                Rule {
+                    condition: Condition::EvenDigits,
+                    action: Action::Split,
+                },
+                Rule {
+                    condition: Condition::Any,
+                    action: Action::Multiply(2024),
+                },
+            ],
+        }
+    }
+
+    /// Parses one `condition: action` rule per line, e.g. for the puzzle:
+    ///
+    /// ```text
+    /// 0: 1
+    /// even: split
+    /// any: *2024
+    /// ```
+    ///
+    /// Conditions are a number, `even`, `odd` or `any` (digit counts), actions a
+    /// replacement number, `split`, `*n` or `+n`. Text after `#` is ignored.
+    fn parse(config: &str) -> Result<Self, Box<dyn Error>> {
+        let mut rules = vec![];
+
+        for line in config.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (condition, action) = line
+                .split_once(':')
+                .ok_or_else(|| format!("Missing ':' in rule '{line}'"))?;
+
+            let condition = match condition.trim() {
+                "even" => Condition::EvenDigits,
+                "odd" => Condition::OddDigits,
+                "any" => Condition::Any,
+                value => Condition::Equals(value.parse()?),
+            };
+
+            let action = action.trim();
+            let action = if action == "split" {
+                Action::Split
+            } else if let Some(factor) = action.strip_prefix('*') {
+                Action::Multiply(factor.trim().parse()?)
+            } else if let Some(summand) = action.strip_prefix('+') {
+                Action::Add(summand.trim().parse()?)
+            } else {
+                Action::Replace(action.parse()?)
+            };
+
+            rules.push(Rule { condition, action });
+        }
+
+        Ok(StoneRules { rules })
+    }
+
+    /// The stones a single stone turns into after one blink
+    fn apply(&self, stone: u64) -> Result<Vec<u64>, StoneOverflow> {
+        let digits = stone.checked_ilog10().unwrap_or(0) + 1;
+
+        let rule = self.rules.iter().find(|rule| match rule.condition {
+            Condition::Equals(value) => stone == value,
+            Condition::EvenDigits => digits.is_multiple_of(2),
+            Condition::OddDigits => !digits.is_multiple_of(2),
+            Condition::Any => true,
+        });
+
+        let stones = match rule.map(|rule| rule.action) {
+            None => vec![stone],
+            Some(Action::Replace(value)) => vec![value],
+            Some(Action::Split) => {
+                let divisor = 10u64.pow(digits / 2);
+                vec![stone / divisor, stone % divisor]
+            }
+            Some(Action::Multiply(factor)) => {
+                vec![stone.checked_mul(factor).ok_or(StoneOverflow(stone))?]
+            }
+            Some(Action::Add(summand)) => {
+                vec![stone.checked_add(summand).ok_or(StoneOverflow(stone))?]
+            }
+        };
+        Ok(stones)
+    }
+}
+
+/// Number of stones per engraved value. Stone order never matters for the
+/// count, so equal stones are only tracked once.
+type Stones = HashMap<u64, BigUint>;
+
+fn parse_stones(input: &str) -> Stones {
+    let mut stones = Stones::new();
+    for number in input.split_whitespace() {
+        *stones
+            .entry(number.parse().expect("Invalid stone"))
+            .or_default() += 1u32;
+    }
+    stones
+}
+
+/// Advances all stones by one generation
+fn blink(rules: &StoneRules, stones: &Stones) -> Result<Stones, StoneOverflow> {
+    let mut next = Stones::with_capacity(stones.len());
+    for (&stone, count) in stones {
+        for new_stone in rules.apply(stone)? {
+            *next.entry(new_stone).or_default() += count;
+        }
+    }
+    Ok(next)
+}
+
+/// Total number of stones after the given number of blinks
+fn count_after(
+    rules: &StoneRules,
+    stones: &Stones,
+    blinks: usize,
+) -> Result<BigUint, StoneOverflow> {
+    let mut stones = stones.clone();
+    for _ in 0..blinks {
+        stones = blink(rules, &stones)?;
+    }
+    Ok(stones
+        .values()
+        .fold(BigUint::zero(), |total, count| total + count))
+}
 
-    let part2 = numbers
-        .iter()
-        .fold(0, |count, &number| count + blink(number, 75));
+fn main() -> Result<(), Box<dyn Error>> {
+    let input = fs::read_to_string("input.txt")?;
+    let stones = parse_stones(&input);
+
+    // Custom rule sets can be passed as a config file
+    let rules = match env::args().nth(1) {
+        Some(path) => StoneRules::parse(&fs::read_to_string(path)?)?,
+        None => StoneRules::puzzle(),
+    };
+
+    let part1 = count_after(&rules, &stones, 25)?;
+    let part2 = count_after(&rules, &stones, 75)?;
 
     println!("Part 1: {}\nPart 2: {}", part1, part2);
+
+    let count = count_after(&rules, &stones, 1000)?.to_string();
+    println!("After 1000 blinks: {} ({} digits)", count, count.len());
+
+    // Stones settle into a finite set of values, which allows skipping ahead
+    const MODULUS: u64 = 1_000_000_007;
+    match TransitionGraph::closure(&rules, &stones, 100_000) {
+        Some(graph) => {
+            let blinks = BigUint::from(10u32).pow(18);
+            // the matrix power is simpler, but cubic in the number of values
+            let count = if graph.len() <= 300 {
+                graph.count_after_matrix(&stones, &blinks, MODULUS)
+            } else {
+                graph.count_after_recurrence(&stones, &blinks, MODULUS)
+            };
+            println!("{} reachable stone values", graph.len());
+            println!("After 10^18 blinks: {} (mod {})", count, MODULUS);
+        }
+        None => println!("Stone values never settle"),
+    }
+
+    Ok(())
 }
 
-#[cached]
-fn blink(number: u64, remaining_blinks: u64) -> u64 {
-    if remaining_blinks == 0 {
-        return 1;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_test_input() -> Stones {
+        let input = fs::read_to_string("test.txt").expect("Failed to read test file");
+        parse_stones(&input)
+    }
+
+    #[test]
+    fn test_blink() {
+        let rules = StoneRules::puzzle();
+        let stones = read_test_input();
+
+        assert_eq!(count_after(&rules, &stones, 6), Ok(BigUint::from(22u32)));
+        assert_eq!(
+            count_after(&rules, &stones, 25),
+            Ok(BigUint::from(55312u32))
+        );
+    }
+
+    #[test]
+    fn test_apply() {
+        let rules = StoneRules::puzzle();
+
+        assert_eq!(rules.apply(0), Ok(vec![1]));
+        assert_eq!(rules.apply(1000), Ok(vec![10, 0]));
+        assert_eq!(rules.apply(99), Ok(vec![9, 9]));
+        assert_eq!(rules.apply(125), Ok(vec![253000]));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let rules = StoneRules::parse("any: *2024\n").unwrap();
+        let stones = parse_stones("1");
+
+        assert_eq!(
+            rules.apply(u64::MAX / 2024),
+            Ok(vec![u64::MAX / 2024 * 2024])
+        );
+        assert_eq!(
+            rules.apply(u64::MAX / 2024 + 1),
+            Err(StoneOverflow(u64::MAX / 2024 + 1))
+        );
+        // 2024^5 still fits, 2024^6 does not
+        assert!(count_after(&rules, &stones, 5).is_ok());
+        assert_eq!(
+            count_after(&rules, &stones, 6),
+            Err(StoneOverflow(2024u64.pow(5)))
+        );
+
+        let rules = StoneRules::parse("any: +1\n").unwrap();
+        assert_eq!(rules.apply(u64::MAX), Err(StoneOverflow(u64::MAX)));
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let config = "# the puzzle rules\n0: 1\neven: split\nany: *2024\n";
+        assert_eq!(StoneRules::parse(config).unwrap(), StoneRules::puzzle());
+
+        let rules = StoneRules::parse("odd: +1\n").unwrap();
+        assert_eq!(rules.apply(7), Ok(vec![8]));
+        assert_eq!(rules.apply(42), Ok(vec![42]));
+
+        assert!(StoneRules::parse("0 -> 1").is_err());
+        assert!(StoneRules::parse("even: halve").is_err());
     }
 
-    if number == 0 {
-        blink(1, remaining_blinks - 1)
-    } else if number.to_string().len() % 2 == 0 {
-        let str_val = number.to_string();
-        let left: u64 = str_val[0..str_val.len() / 2].parse().unwrap();
-        let right: u64 = str_val[str_val.len() / 2..str_val.len()].parse().unwrap();
+    #[test]
+    fn test_many_blinks() {
+        let rules = StoneRules::puzzle();
+        let stones = read_test_input();
 
-        blink(left, remaining_blinks - 1) + blink(right, remaining_blinks - 1)
-    } else {
-        blink(number * 2024, remaining_blinks - 1)
+        // far beyond what fits into a u128
+        let count = count_after(&rules, &stones, 1000).unwrap();
+        assert!(count.bits() > 128);
+        assert_eq!(
+            count_after(&rules, &stones, 75),
+            Ok(BigUint::from(65601038650482u64))
+        );
     }
 }
@@ -0,0 +1,433 @@
+use std::collections::{HashMap, VecDeque};
+
+use num::{BigUint, ToPrimitive};
+
+use crate::{StoneRules, Stones};
+
+/// The finite set of stone values reachable from some starting stones, and
+/// which values each of them turns into on a blink.
+pub struct TransitionGraph {
+    values: Vec<u64>,
+    index: HashMap<u64, usize>,
+    /// Indices of the stones each value turns into, with repetitions
+    successors: Vec<Vec<usize>>,
+}
+
+impl TransitionGraph {
+    /// Collects every value reachable from `stones` under `rules`.
+    ///
+    /// Returns `None` if there are more than `limit` of them or a value
+    /// outgrows a `u64`, e.g. for rules under which values keep growing forever.
+    pub fn closure(rules: &StoneRules, stones: &Stones, limit: usize) -> Option<Self> {
+        let mut values: Vec<u64> = stones.keys().copied().collect();
+        values.sort_unstable();
+        let mut index: HashMap<u64, usize> =
+            values.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+        let mut successors = vec![];
+
+        let mut to_visit: VecDeque<usize> = (0..values.len()).collect();
+        while let Some(i) = to_visit.pop_front() {
+            let next = rules
+                .apply(values[i])
+                .ok()?
+                .into_iter()
+                .map(|value| {
+                    *index.entry(value).or_insert_with(|| {
+                        values.push(value);
+                        to_visit.push_back(values.len() - 1);
+                        values.len() - 1
+                    })
+                })
+                .collect();
+
+            // values are visited in index order
+            successors.push(next);
+
+            if values.len() > limit {
+                return None;
+            }
+        }
+
+        Some(TransitionGraph {
+            values,
+            index,
+            successors,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// `matrix[i][j]` is the number of stones with the `j`-th value that one
+    /// stone with the `i`-th value turns into.
+    pub fn matrix(&self, modulus: u64) -> Matrix {
+        let mut matrix = Matrix::zero(self.len(), modulus);
+        for (i, next) in self.successors.iter().enumerate() {
+            for &j in next {
+                matrix.add(i, j, 1);
+            }
+        }
+        matrix
+    }
+
+    /// Stone counts per value as a vector, reduced by `modulus`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stones` contains a value outside the closure.
+    fn counts(&self, stones: &Stones, modulus: u64) -> Vec<u64> {
+        let mut counts = vec![0; self.len()];
+        for (value, count) in stones {
+            let i = *self.index.get(value).expect("Stone outside of the closure");
+            counts[i] = (count % modulus).to_u64().unwrap();
+        }
+        counts
+    }
+
+    /// Number of stones after `blinks` blinks, modulo `modulus`, as
+    /// `counts · M^blinks · 1` with the transition matrix `M`.
+    ///
+    /// Takes `O(n³ log blinks)` for `n` values in the closure, so this is only
+    /// practical for small closures; see `count_after_recurrence` otherwise.
+    pub fn count_after_matrix(&self, stones: &Stones, blinks: &BigUint, modulus: u64) -> u64 {
+        let counts = self.counts(stones, modulus);
+        let power = self.matrix(modulus).pow(blinks);
+
+        // row sums are the number of stones a single stone turns into
+        counts.iter().enumerate().fold(0, |total, (i, &count)| {
+            let stones_from_one = power.row(i).iter().fold(0, |sum, &x| (sum + x) % modulus);
+            (total + count * stones_from_one) % modulus
+        })
+    }
+
+    /// Same as `count_after_matrix`, for a prime `modulus`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `modulus` is not prime, as the recurrence is found by
+    /// dividing modulo `modulus`.
+    ///
+    /// The stone totals satisfy a linear recurrence of order at most `n` (the
+    /// characteristic polynomial of the transition matrix), which is recovered
+    /// from the first `2n` totals with Berlekamp-Massey. `x^blinks` is then
+    /// reduced modulo that recurrence's polynomial, taking `O(n² log blinks)`.
+    pub fn count_after_recurrence(&self, stones: &Stones, blinks: &BigUint, modulus: u64) -> u64 {
+        check_modulus(modulus);
+        assert!(is_prime(modulus), "Modulus must be prime");
+        let mut counts = self.counts(stones, modulus);
+        let mut totals = Vec::with_capacity(2 * self.len() + 1);
+
+        for _ in 0..=2 * self.len() {
+            totals.push(counts.iter().fold(0, |sum, &count| (sum + count) % modulus));
+
+            let mut next = vec![0; self.len()];
+            for (i, &count) in counts.iter().enumerate() {
+                for &j in &self.successors[i] {
+                    next[j] = (next[j] + count) % modulus;
+                }
+            }
+            counts = next;
+        }
+
+        if let Some(blinks) = blinks.to_usize().filter(|&blinks| blinks < totals.len()) {
+            return totals[blinks];
+        }
+
+        let recurrence = berlekamp_massey(&totals, modulus);
+        let polynomial = power_of_x(blinks, &recurrence, modulus);
+
+        polynomial
+            .iter()
+            .zip(&totals)
+            .fold(0, |sum, (&coefficient, &total)| {
+                (sum + coefficient * total % modulus) % modulus
+            })
+    }
+}
+
+/// Square matrix with entries modulo `modulus`.
+pub struct Matrix {
+    size: usize,
+    modulus: u64,
+    entries: Vec<u64>,
+}
+
+impl Matrix {
+    fn zero(size: usize, modulus: u64) -> Self {
+        check_modulus(modulus);
+        Matrix {
+            size,
+            modulus,
+            entries: vec![0; size * size],
+        }
+    }
+
+    fn identity(size: usize, modulus: u64) -> Self {
+        let mut matrix = Matrix::zero(size, modulus);
+        for i in 0..size {
+            matrix.add(i, i, 1);
+        }
+        matrix
+    }
+
+    fn add(&mut self, i: usize, j: usize, value: u64) {
+        let entry = &mut self.entries[i * self.size + j];
+        *entry = (*entry + value) % self.modulus;
+    }
+
+    fn row(&self, i: usize) -> &[u64] {
+        &self.entries[i * self.size..(i + 1) * self.size]
+    }
+
+    fn mul(&self, other: &Matrix) -> Matrix {
+        let mut result = Matrix::zero(self.size, self.modulus);
+        let mut row = vec![0u128; self.size];
+
+        for i in 0..self.size {
+            row.fill(0);
+            for (k, &a) in self.row(i).iter().enumerate() {
+                if a != 0 {
+                    for (sum, &b) in row.iter_mut().zip(other.row(k)) {
+                        *sum += (a * b) as u128;
+                    }
+                }
+            }
+            for (entry, sum) in result.entries[i * self.size..].iter_mut().zip(&row) {
+                *entry = (sum % self.modulus as u128) as u64;
+            }
+        }
+
+        result
+    }
+
+    /// Raises the matrix to the given power by repeated squaring.
+    pub fn pow(&self, exponent: &BigUint) -> Matrix {
+        let mut result = Matrix::identity(self.size, self.modulus);
+        for bit in (0..exponent.bits()).rev() {
+            result = result.mul(&result);
+            if exponent.bit(bit) {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+}
+
+/// Finds the shortest recurrence `s[n] = Σ c[i] · s[n - 1 - i]` generating `s`
+/// modulo the prime `modulus`.
+fn berlekamp_massey(s: &[u64], modulus: u64) -> Vec<u64> {
+    let inverse = |x: u64| pow_mod(x, modulus - 2, modulus);
+
+    // connection polynomials, with s[n] + Σ c[i] · s[n - i] = 0
+    let mut current = vec![1];
+    let mut previous = vec![1];
+    let mut length = 0;
+    let mut shift = 1;
+    let mut previous_discrepancy = 1;
+
+    for n in 0..s.len() {
+        let discrepancy = (1..=length).fold(s[n], |d, i| (d + current[i] * s[n - i]) % modulus);
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+
+        let factor = discrepancy * inverse(previous_discrepancy) % modulus;
+        let before = current.clone();
+
+        current.resize(current.len().max(previous.len() + shift), 0);
+        for (i, &p) in previous.iter().enumerate() {
+            current[i + shift] = (current[i + shift] + modulus - factor * p % modulus) % modulus;
+        }
+
+        if 2 * length <= n {
+            length = n + 1 - length;
+            previous = before;
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            shift += 1;
+        }
+    }
+
+    current.resize(length + 1, 0);
+    current[1..]
+        .iter()
+        .map(|&c| (modulus - c) % modulus)
+        .collect()
+}
+
+/// `x^exponent` modulo the characteristic polynomial of `recurrence`, i.e. the
+/// coefficients expressing the `exponent`-th term through the first ones.
+fn power_of_x(exponent: &BigUint, recurrence: &[u64], modulus: u64) -> Vec<u64> {
+    let order = recurrence.len();
+    if order == 0 {
+        return vec![];
+    }
+
+    // x^k = Σ c[i] · x^(k - 1 - i) for k >= order
+    let reduce = |mut polynomial: Vec<u64>| {
+        for k in (order..polynomial.len()).rev() {
+            let coefficient = polynomial[k];
+            if coefficient != 0 {
+                for (i, &c) in recurrence.iter().enumerate() {
+                    let target = &mut polynomial[k - 1 - i];
+                    *target = (*target + coefficient * c) % modulus;
+                }
+            }
+        }
+        polynomial.truncate(order);
+        polynomial
+    };
+
+    let mut result = vec![0; order];
+    result[0] = 1;
+
+    for bit in (0..exponent.bits()).rev() {
+        let mut square = vec![0u128; 2 * order - 1];
+        for (i, &a) in result.iter().enumerate() {
+            if a != 0 {
+                for (j, &b) in result.iter().enumerate() {
+                    square[i + j] += (a * b) as u128;
+                }
+            }
+        }
+        result = reduce(
+            square
+                .into_iter()
+                .map(|x| (x % modulus as u128) as u64)
+                .collect(),
+        );
+
+        if exponent.bit(bit) {
+            result.insert(0, 0);
+            result = reduce(result);
+        }
+    }
+
+    result
+}
+
+/// Products of two residues have to fit into a `u64`.
+fn check_modulus(modulus: u64) {
+    assert!(
+        modulus > 1 && modulus <= u32::MAX as u64,
+        "Modulus must fit into 32 bits"
+    );
+}
+
+fn is_prime(n: u64) -> bool {
+    n > 1
+        && (2..)
+            .take_while(|d| d * d <= n)
+            .all(|d| !n.is_multiple_of(d))
+}
+
+fn pow_mod(mut base: u64, mut exponent: u64, modulus: u64) -> u64 {
+    let mut result = 1;
+    base %= modulus;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result * base % modulus;
+        }
+        base = base * base % modulus;
+        exponent >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{count_after, parse_stones};
+
+    const MODULUS: u64 = 1_000_000_007;
+
+    /// Plain simulation, but with counts modulo `MODULUS`
+    fn count_mod(rules: &StoneRules, stones: &Stones, blinks: usize) -> u64 {
+        let mut counts: HashMap<u64, u64> = stones
+            .iter()
+            .map(|(&stone, count)| (stone, (count % MODULUS).to_u64().unwrap()))
+            .collect();
+        for _ in 0..blinks {
+            let mut next = HashMap::new();
+            for (&stone, &count) in &counts {
+                for new_stone in rules.apply(stone).unwrap() {
+                    let entry = next.entry(new_stone).or_insert(0);
+                    *entry = (*entry + count) % MODULUS;
+                }
+            }
+            counts = next;
+        }
+        counts
+            .values()
+            .fold(0, |sum, &count| (sum + count) % MODULUS)
+    }
+
+    #[test]
+    fn test_closure() {
+        let rules = StoneRules::parse("0: 1\n1: 2\n2: 0\n").unwrap();
+        let graph = TransitionGraph::closure(&rules, &parse_stones("0"), 10).unwrap();
+        assert_eq!(graph.len(), 3);
+
+        let rules = StoneRules::parse("any: +1\n").unwrap();
+        assert!(TransitionGraph::closure(&rules, &parse_stones("0"), 100).is_none());
+
+        // overflows after five blinks, long before the limit is reached
+        let rules = StoneRules::parse("any: *2024\n").unwrap();
+        assert!(TransitionGraph::closure(&rules, &parse_stones("1"), 100).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Modulus must be prime")]
+    fn test_recurrence_needs_prime() {
+        let rules = StoneRules::parse("0: 1\n1: 2\n2: 0\n").unwrap();
+        let stones = parse_stones("0");
+        let graph = TransitionGraph::closure(&rules, &stones, 10).unwrap();
+
+        graph.count_after_recurrence(&stones, &BigUint::from(100u32), 1 << 20);
+    }
+
+    #[test]
+    fn test_matrix_matches_simulation() {
+        // the rules close over a small set of values
+        let rules = StoneRules::parse("0: 1\neven: split\nany: *11\n").unwrap();
+        let stones = parse_stones("0 7 13");
+        let graph = TransitionGraph::closure(&rules, &stones, 1000).unwrap();
+
+        for blinks in [0, 1, 5, 40] {
+            let expected = count_after(&rules, &stones, blinks).unwrap() % MODULUS;
+            let blinks = BigUint::from(blinks);
+
+            assert_eq!(
+                BigUint::from(graph.count_after_matrix(&stones, &blinks, MODULUS)),
+                expected
+            );
+            assert_eq!(
+                BigUint::from(graph.count_after_recurrence(&stones, &blinks, MODULUS)),
+                expected
+            );
+        }
+
+        let blinks = BigUint::from(10u32).pow(30);
+        assert_eq!(
+            graph.count_after_matrix(&stones, &blinks, MODULUS),
+            graph.count_after_recurrence(&stones, &blinks, MODULUS)
+        );
+    }
+
+    #[test]
+    fn test_recurrence_puzzle_rules() {
+        let rules = StoneRules::puzzle();
+        let stones = parse_stones("125 17");
+        let graph = TransitionGraph::closure(&rules, &stones, 10_000).unwrap();
+
+        // beyond the precomputed terms, so the recurrence has to be used
+        let blinks = 2 * graph.len() + 10;
+        assert_eq!(
+            graph.count_after_recurrence(&stones, &BigUint::from(blinks), MODULUS),
+            count_mod(&rules, &stones, blinks)
+        );
+    }
+}
@@ -1,97 +1,525 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fs::File,
+    io::{self, BufWriter, Write},
+};
 
 // Directions for moving in the grid: right, down, left, up
 const DIRS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
 
-fn main() {
-    // Read the input file and parse it into a grid
-    let input = std::fs::read_to_string("input.txt").expect("Failed to read input file");
-    let grid: HashMap<(i32, i32), char> = input
-        .lines()
-        .enumerate() // Attach line indices
-        .flat_map(|(y, line)| {
-            line.chars()
-                .enumerate() // Attach character indices
-                .map(move |(x, c)| ((x as i32, y as i32), c)) // Map (x, y) -> character
-        })
-        .collect();
+type Pos = (i32, i32);
 
-    let mut fenced = HashSet::new(); // Track visited regions
-    let mut costs_part1 = 0; // Cost for Part 1
-    let mut costs_part2 = 0; // Cost for Part 2
+/// Dense, row-major garden plot map
+struct Grid {
+    width: i32,
+    height: i32,
+    plants: Vec<char>,
+}
 
-    // Iterate over each cell in the grid
-    for (&(x, y), &plant) in &grid {
-        // Skip already fenced regions
-        if fenced.contains(&(x, y)) {
-            continue;
+impl Grid {
+    fn parse(input: &str) -> Self {
+        let lines: Vec<&str> = input.lines().collect();
+
+        Grid {
+            width: lines.first().map_or(0, |line| line.len() as i32),
+            height: lines.len() as i32,
+            plants: lines.iter().flat_map(|line| line.chars()).collect(),
+        }
+    }
+
+    fn contains(&self, (x, y): Pos) -> bool {
+        x >= 0 && y >= 0 && x < self.width && y < self.height
+    }
+
+    fn index(&self, (x, y): Pos) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    fn get(&self, pos: Pos) -> Option<char> {
+        self.contains(pos).then(|| self.plants[self.index(pos)])
+    }
+
+    fn positions(&self) -> impl Iterator<Item = Pos> + '_ {
+        (0..self.height).flat_map(move |y| (0..self.width).map(move |x| (x, y)))
+    }
+}
+
+/// A connected area of the same plant
+struct Region {
+    plant: char,
+    cells: HashSet<Pos>,
+    /// Smallest and largest corner of the bounding box, inclusive
+    bounds: (Pos, Pos),
+    /// Index of the region that completely surrounds this one, if any
+    enclosed_by: Option<usize>,
+}
+
+impl Region {
+    fn new(plant: char, cells: HashSet<Pos>) -> Self {
+        let min = cells.iter().fold((i32::MAX, i32::MAX), |min, &(x, y)| {
+            (min.0.min(x), min.1.min(y))
+        });
+        let max = cells.iter().fold((i32::MIN, i32::MIN), |max, &(x, y)| {
+            (max.0.max(x), max.1.max(y))
+        });
+
+        Region {
+            plant,
+            cells,
+            bounds: (min, max),
+            enclosed_by: None,
         }
+    }
+
+    fn area(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Number of unit fences around the region
+    fn perimeter(&self) -> usize {
+        self.cells
+            .iter()
+            .flat_map(|&(x, y)| DIRS.iter().map(move |&(dx, dy)| (x + dx, y + dy)))
+            .filter(|pos| !self.cells.contains(pos))
+            .count()
+    }
+
+    /// Number of straight fence sections, which equals the number of corners
+    fn sides(&self) -> usize {
+        let mut corners = 0;
 
-        // Initialize traversal for the current region
-        let mut queue = vec![(x, y)];
-        let mut area = 0; // Area of the current region
-        let mut fences = HashSet::new(); // Fences required for this region
+        for &(x, y) in &self.cells {
+            // each pair of neighbouring directions spans one corner of the cell
+            for i in 0..4 {
+                let (ax, ay) = DIRS[i];
+                let (bx, by) = DIRS[(i + 1) % 4];
+                let a = self.cells.contains(&(x + ax, y + ay));
+                let b = self.cells.contains(&(x + bx, y + by));
+                let diagonal = self.cells.contains(&(x + ax + bx, y + ay + by));
 
-        // Traverse the region using a queue (BFS-like approach)
-        while let Some((x, y)) = queue.pop() {
-            // Skip if already visited
-            if !fenced.insert((x, y)) {
-                continue;
+                // convex corners have neither neighbour, concave ones both
+                // neighbours but not the cell in between
+                if (!a && !b) || (a && b && !diagonal) {
+                    corners += 1;
+                }
             }
+        }
 
-            area += 1; // Increase area size
+        corners
+    }
 
-            // Check all 4 directions
+    /// The straight fence sections, as `(dir, from, to)` with the ends given in
+    /// grid corner coordinates and `dir` pointing out of the region.
+    ///
+    /// Unit fences facing the same way are joined while they are adjacent along
+    /// the same line, so this yields as many sections as `sides` counts.
+    fn fence_sides(&self) -> Vec<(usize, Pos, Pos)> {
+        // unit fences grouped by facing direction and the line they lie on,
+        // keyed by their position along that line
+        let mut lines: HashMap<(usize, i32), Vec<i32>> = HashMap::new();
+
+        for &(x, y) in &self.cells {
             for (dir, &(dx, dy)) in DIRS.iter().enumerate() {
-                let new_x = x + dx;
-                let new_y = y + dy;
-
-                // If the neighboring cell belongs to the same plant, add it to the queue
-                if grid.get(&(new_x, new_y)) == Some(&plant) {
-                    queue.push((new_x, new_y));
-                } else {
-                    // Otherwise, mark the boundary as a fence
-                    fences.insert((x, y, dir));
+                if self.cells.contains(&(x + dx, y + dy)) {
+                    continue;
+                }
+                match dir {
+                    0 => lines.entry((dir, x + 1)).or_default().push(y),
+                    1 => lines.entry((dir, y + 1)).or_default().push(x),
+                    2 => lines.entry((dir, x)).or_default().push(y),
+                    _ => lines.entry((dir, y)).or_default().push(x),
                 }
             }
         }
 
-        // Part 1: Add cost based on the area and number of fences
-        costs_part1 += area * fences.len();
+        let mut sides = vec![];
+        for ((dir, line), mut offsets) in lines {
+            offsets.sort_unstable();
 
-        // Part 2: Calculate joined fences to reduce redundant fencing
-        let mut joined_fences = 0; // Count of shared fences
-        let mut analyzed_fences = HashSet::new(); // Track analyzed fences
+            let mut start = offsets[0];
+            for (i, &offset) in offsets.iter().enumerate() {
+                let run_ends = offsets.get(i + 1) != Some(&(offset + 1));
+                if run_ends {
+                    // vertical fences for left/right, horizontal ones otherwise
+                    let (from, to) = if dir % 2 == 0 {
+                        ((line, start), (line, offset + 1))
+                    } else {
+                        ((start, line), (offset + 1, line))
+                    };
+                    sides.push((dir, from, to));
 
-        for &(x, y, dir) in &fences {
-            // Determine perpendicular directions (left and right of the current fence)
-            let perpendicular_directions = [(dir + 1) % 4, (dir + 3) % 4];
+                    if let Some(&next) = offsets.get(i + 1) {
+                        start = next;
+                    }
+                }
+            }
+        }
+
+        sides
+    }
+
+    fn bounding_box(&self) -> (Pos, Pos) {
+        self.bounds
+    }
+
+    fn enclosed_by(&self) -> Option<usize> {
+        self.enclosed_by
+    }
+
+    fn price(&self) -> usize {
+        self.area() * self.perimeter()
+    }
 
-            // Skip if the fence has already been analyzed
-            if !analyzed_fences.insert((x, y, dir)) {
-                continue;
+    fn bulk_price(&self) -> usize {
+        self.area() * self.sides()
+    }
+}
+
+/// Union-find over cell indices, with path halving and union by size
+struct DisjointSet {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(len: usize) -> Self {
+        DisjointSet {
+            parent: (0..len).collect(),
+            size: vec![1; len],
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+}
+
+/// Labels every cell with the index of its region.
+///
+/// Each cell is joined with its right and lower neighbour if they grow the
+/// same plant; regions are numbered in order of their first cell.
+fn label_regions(grid: &Grid) -> Vec<usize> {
+    let mut sets = DisjointSet::new(grid.plants.len());
+
+    for (x, y) in grid.positions() {
+        let plant = grid.get((x, y));
+        for next in [(x + 1, y), (x, y + 1)] {
+            if grid.get(next) == plant {
+                sets.union(grid.index((x, y)), grid.index(next));
             }
+        }
+    }
+
+    let mut ids = HashMap::new();
+    (0..grid.plants.len())
+        .map(|i| {
+            let root = sets.find(i);
+            let next_id = ids.len();
+            *ids.entry(root).or_insert(next_id)
+        })
+        .collect()
+}
 
-            // Check along the perpendicular directions for shared fences
-            for &perpendicular_dir in &perpendicular_directions {
-                let mut pos = (x + DIRS[perpendicular_dir].0, y + DIRS[perpendicular_dir].1);
-
-                // Traverse along the direction as long as the plant and fence match
-                while grid.get(&pos) == Some(&plant) && fences.contains(&(pos.0, pos.1, dir)) {
-                    analyzed_fences.insert((pos.0, pos.1, dir));
-                    joined_fences += 1;
-                    pos = (
-                        pos.0 + DIRS[perpendicular_dir].0,
-                        pos.1 + DIRS[perpendicular_dir].1,
-                    );
+/// Splits the grid into its regions, indexed by their label
+fn regions(grid: &Grid) -> Vec<Region> {
+    regions_from_labels(grid, &label_regions(grid))
+}
+
+fn regions_from_labels(grid: &Grid, labels: &[usize]) -> Vec<Region> {
+    let region_count = labels.iter().max().map_or(0, |&max| max + 1);
+    let mut cells: Vec<(char, HashSet<Pos>)> = vec![(' ', HashSet::new()); region_count];
+
+    for pos in grid.positions() {
+        let (plant, region) = &mut cells[labels[grid.index(pos)]];
+        *plant = grid.get(pos).expect("Position inside the grid");
+        region.insert(pos);
+    }
+
+    let mut regions: Vec<Region> = cells
+        .into_iter()
+        .map(|(plant, cells)| Region::new(plant, cells))
+        .collect();
+
+    for region in &mut regions {
+        region.enclosed_by = find_enclosing(grid, labels, region);
+    }
+
+    regions
+}
+
+/// Finds the region all of `region`'s outer neighbours belong to.
+///
+/// The outer neighbours are found by flooding inwards from a ring just outside
+/// the bounding box, so holes inside the region are ignored. Regions touching
+/// the edge of the grid are never enclosed.
+fn find_enclosing(grid: &Grid, labels: &[usize], region: &Region) -> Option<usize> {
+    let ((min_x, min_y), (max_x, max_y)) = region.bounds;
+    let (min, max) = ((min_x - 1, min_y - 1), (max_x + 1, max_y + 1));
+    if !grid.contains(min) || !grid.contains(max) {
+        return None;
+    }
+
+    let inside = |(x, y): Pos| x >= min.0 && x <= max.0 && y >= min.1 && y <= max.1;
+    let mut queue: Vec<Pos> = (min.0..=max.0)
+        .flat_map(|x| [(x, min.1), (x, max.1)])
+        .chain((min.1..=max.1).flat_map(|y| [(min.0, y), (max.0, y)]))
+        .collect();
+    let mut outside = HashSet::new();
+    let mut enclosing = None;
+
+    while let Some((x, y)) = queue.pop() {
+        if !outside.insert((x, y)) {
+            continue;
+        }
+
+        for &(dx, dy) in &DIRS {
+            let next = (x + dx, y + dy);
+            if region.cells.contains(&next) {
+                // (x, y) is an outer neighbour
+                let label = labels[grid.index((x, y))];
+                if enclosing.is_some_and(|enclosing| enclosing != label) {
+                    return None;
                 }
+                enclosing = Some(label);
+            } else if inside(next) && !outside.contains(&next) {
+                queue.push(next);
             }
         }
+    }
+
+    enclosing
+}
 
-        // Part 2: Add cost accounting for joined fences
-        costs_part2 += area * (fences.len() - joined_fences);
+/// Writes the region label of every cell, one grid row per line.
+fn write_csv(grid: &Grid, labels: &[usize], out: &mut impl Write) -> io::Result<()> {
+    for row in labels.chunks(grid.width as usize) {
+        let row: Vec<String> = row.iter().map(|label| label.to_string()).collect();
+        writeln!(out, "{}", row.join(","))?;
     }
+    Ok(())
+}
+
+/// Escapes a plant for use in XML text.
+fn xml_escape(plant: char) -> String {
+    match plant {
+        '<' => "&lt;".to_string(),
+        '>' => "&gt;".to_string(),
+        '&' => "&amp;".to_string(),
+        _ => plant.to_string(),
+    }
+}
+
+/// Draws the regions as coloured cells with every fence side as its own line.
+///
+/// Sides are shortened at both ends and moved slightly into their region, so
+/// joined sections can be told apart and counted against `Region::sides`.
+fn write_svg(
+    grid: &Grid,
+    labels: &[usize],
+    regions: &[Region],
+    out: &mut impl Write,
+) -> io::Result<()> {
+    const CELL: f64 = 10.0;
+    const INSET: f64 = 1.5;
+
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">"#,
+        grid.width as f64 * CELL,
+        grid.height as f64 * CELL
+    )?;
+
+    for (x, y) in grid.positions() {
+        let label = labels[grid.index((x, y))];
+        // spread neighbouring labels around the colour wheel
+        let hue = (label * 137) % 360;
+        writeln!(
+            out,
+            r#"<rect x="{}" y="{}" width="{CELL}" height="{CELL}" fill="hsl({hue}, 60%, 75%)"><title>{} #{label}</title></rect>"#,
+            x as f64 * CELL,
+            y as f64 * CELL,
+            xml_escape(regions[label].plant)
+        )?;
+    }
+
+    for region in regions {
+        for (dir, from, to) in region.fence_sides() {
+            // move inwards, against the direction the fence faces
+            let (dx, dy) = DIRS[dir];
+            let shift = (-dx as f64 * INSET, -dy as f64 * INSET);
+            // shorten along the fence
+            let (ax, ay) = DIRS[(dir + 1) % 4];
+            let along = (ax.abs() as f64 * INSET, ay.abs() as f64 * INSET);
+
+            writeln!(
+                out,
+                r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black" stroke-width="1"/>"#,
+                from.0 as f64 * CELL + shift.0 + along.0,
+                from.1 as f64 * CELL + shift.1 + along.1,
+                to.0 as f64 * CELL + shift.0 - along.0,
+                to.1 as f64 * CELL + shift.1 - along.1
+            )?;
+        }
+    }
+
+    writeln!(out, "</svg>")
+}
+
+fn main() {
+    // Read the input file and parse it into a grid
+    let input = std::fs::read_to_string("input.txt").expect("Failed to read input file");
+    let grid = Grid::parse(&input);
+    let regions = regions(&grid);
+
+    let costs_part1: usize = regions.iter().map(Region::price).sum();
+    let costs_part2: usize = regions.iter().map(Region::bulk_price).sum();
 
     // Print results for both parts
     println!("Part 1: {}\nPart 2: {}", costs_part1, costs_part2);
+
+    let enclosed = regions
+        .iter()
+        .filter(|region| region.enclosed_by().is_some())
+        .count();
+    println!("{} regions, {} enclosed", regions.len(), enclosed);
+
+    if let Some(largest) = regions.iter().max_by_key(|region| region.area()) {
+        let ((min_x, min_y), (max_x, max_y)) = largest.bounding_box();
+        println!(
+            "Largest region: {} with area {}, spanning ({min_x}, {min_y}) to ({max_x}, {max_y})",
+            largest.plant,
+            largest.area()
+        );
+    }
+
+    // Optionally export the labelled map as <prefix>.csv and <prefix>.svg
+    if let Some(prefix) = env::args().nth(1) {
+        let labels = label_regions(&grid);
+
+        let file = File::create(format!("{prefix}.csv")).expect("Failed to create CSV file");
+        write_csv(&grid, &labels, &mut BufWriter::new(file)).expect("Failed to write CSV file");
+
+        let file = File::create(format!("{prefix}.svg")).expect("Failed to create SVG file");
+        write_svg(&grid, &labels, &regions, &mut BufWriter::new(file))
+            .expect("Failed to write SVG file");
+
+        println!("Wrote {prefix}.csv and {prefix}.svg");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prices(input: &str) -> (usize, usize) {
+        let regions = regions(&Grid::parse(input));
+        (
+            regions.iter().map(Region::price).sum(),
+            regions.iter().map(Region::bulk_price).sum(),
+        )
+    }
+
+    #[test]
+    fn test_prices() {
+        let input = std::fs::read_to_string("test.txt").expect("Failed to read test file");
+
+        assert_eq!(prices(&input), (1930, 1206));
+        assert_eq!(prices("AAAA\nBBCD\nBBCC\nEEEC"), (140, 80));
+        assert_eq!(prices("EEEEE\nEXXXX\nEEEEE\nEXXXX\nEEEEE").1, 236);
+    }
+
+    #[test]
+    fn test_region() {
+        let grid = Grid::parse("AAAA\nBBCD\nBBCC\nEEEC");
+        let regions = regions(&grid);
+        let c = regions.iter().find(|region| region.plant == 'C').unwrap();
+
+        assert_eq!(c.area(), 4);
+        assert_eq!(c.perimeter(), 10);
+        assert_eq!(c.sides(), 8);
+        assert_eq!(c.bounding_box(), ((2, 1), (3, 3)));
+    }
+
+    #[test]
+    fn test_enclosed() {
+        let grid = Grid::parse("AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA");
+        let regions = regions(&grid);
+
+        assert_eq!(regions.iter().map(Region::bulk_price).sum::<usize>(), 368);
+        for region in &regions {
+            match region.plant {
+                'A' => assert_eq!(region.enclosed_by(), None),
+                _ => assert_eq!(regions[region.enclosed_by().unwrap()].plant, 'A'),
+            }
+        }
+
+        // the middle X is inside O, which is itself inside the outer X
+        let grid = Grid::parse("XXXXX\nXOOOX\nXOXOX\nXOOOX\nXXXXX");
+        let regions = super::regions(&grid);
+        let labels: Vec<char> = regions.iter().map(|region| region.plant).collect();
+        assert_eq!(labels, vec!['X', 'O', 'X']);
+        assert_eq!(regions[0].enclosed_by(), None);
+        assert_eq!(regions[1].enclosed_by(), Some(0));
+        assert_eq!(regions[2].enclosed_by(), Some(1));
+    }
+
+    #[test]
+    fn test_labels() {
+        let grid = Grid::parse("AAAA\nBBCD\nBBCC\nEEEC");
+        let labels = label_regions(&grid);
+
+        assert_eq!(labels, vec![0, 0, 0, 0, 1, 1, 2, 3, 1, 1, 2, 2, 4, 4, 4, 2]);
+
+        let mut csv = vec![];
+        write_csv(&grid, &labels, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "0,0,0,0\n1,1,2,3\n1,1,2,2\n4,4,4,2\n"
+        );
+    }
+
+    #[test]
+    fn test_fence_sides() {
+        let input = std::fs::read_to_string("test.txt").expect("Failed to read test file");
+        let grid = Grid::parse(&input);
+        let labels = label_regions(&grid);
+        let regions = regions_from_labels(&grid, &labels);
+
+        for region in &regions {
+            assert_eq!(region.fence_sides().len(), region.sides());
+        }
+
+        let mut svg = vec![];
+        write_svg(&grid, &labels, &regions, &mut svg).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        let sides: usize = regions.iter().map(Region::sides).sum();
+        assert_eq!(svg.matches("<line").count(), sides);
+        assert_eq!(svg.matches("<rect").count(), 100);
+
+        let grid = Grid::parse("<&\n<&");
+        let labels = label_regions(&grid);
+        let regions = regions_from_labels(&grid, &labels);
+        let mut svg = vec![];
+        write_svg(&grid, &labels, &regions, &mut svg).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.contains("<title>&lt; #0</title>"));
+        assert!(svg.contains("<title>&amp; #1</title>"));
+    }
 }
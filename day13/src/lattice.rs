@@ -0,0 +1,460 @@
+//! Exact solver for small integer programs of the form
+//! `minimize costs · n` subject to `matrix · n = target`, `0 <= n <= max`.
+//!
+//! The integer solutions of the equations form a lattice `n_0 + K t`, found by
+//! column-reducing `matrix` with unimodular operations. The remaining problem
+//! over `t` is solved by branch and bound, with LP relaxations solved exactly
+//! by enumerating the vertices of the (few-dimensional) polytope.
+
+use std::{
+    cmp::Ordering,
+    ops::{Add, Div, Mul, Sub},
+};
+
+/// Finds the cheapest non-negative integer `n` with `matrix · n = target`,
+/// where `matrix` has one row per dimension and one column per variable.
+///
+/// `costs` must be positive, which keeps the optimum finite.
+pub fn minimize(
+    matrix: &[Vec<i128>],
+    target: &[i128],
+    costs: &[i128],
+    max: Option<i128>,
+) -> Option<Vec<i128>> {
+    assert!(costs.iter().all(|&cost| cost > 0), "Costs must be positive");
+
+    let (particular, kernel) = solve_lattice(matrix, target)?;
+    let variables = costs.len();
+    let free = kernel.len();
+
+    // n = particular + Σ t_j kernel[j] has to stay within 0..=max
+    let mut constraints = vec![];
+    for i in 0..variables {
+        let coefficients: Vec<i128> = kernel.iter().map(|column| column[i]).collect();
+        if let Some(max) = max {
+            constraints.push(Constraint {
+                coefficients: coefficients.iter().map(|c| -c).collect(),
+                bound: particular[i] - max,
+            });
+        }
+        constraints.push(Constraint {
+            coefficients,
+            bound: -particular[i],
+        });
+    }
+
+    let t = if free == 0 {
+        let fits = constraints.iter().all(|constraint| constraint.bound <= 0);
+        fits.then(Vec::new)?
+    } else {
+        let objective: Vec<i128> = kernel
+            .iter()
+            .map(|column| column.iter().zip(costs).map(|(x, cost)| x * cost).sum())
+            .collect();
+
+        let mut best = None;
+        branch_and_bound(constraints, &objective, &mut best);
+        best?.1
+    };
+
+    Some(
+        (0..variables)
+            .map(|i| {
+                particular[i]
+                    + kernel
+                        .iter()
+                        .zip(&t)
+                        .map(|(column, t)| column[i] * t)
+                        .sum::<i128>()
+            })
+            .collect(),
+    )
+}
+
+/// Returns `(n_0, K)` such that the integer solutions of `matrix · n = target`
+/// are exactly `n_0 + K t` for integer `t`, or `None` if there are none.
+fn solve_lattice(matrix: &[Vec<i128>], target: &[i128]) -> Option<(Vec<i128>, Vec<Vec<i128>>)> {
+    let rows = matrix.len();
+    let columns = matrix.first().map_or(0, |row| row.len());
+
+    // column operations on `reduced` are mirrored in `unimodular`, so that
+    // matrix · unimodular = reduced at all times
+    let mut reduced = matrix.to_vec();
+    let mut unimodular: Vec<Vec<i128>> = (0..columns)
+        .map(|i| (0..columns).map(|j| (i == j) as i128).collect())
+        .collect();
+
+    let combine =
+        |m: &mut Vec<Vec<i128>>, p: usize, j: usize, (s, t, u, v): (i128, i128, i128, i128)| {
+            for row in m.iter_mut() {
+                let (x, y) = (row[p], row[j]);
+                row[p] = s * x + t * y;
+                row[j] = u * x + v * y;
+            }
+        };
+
+    let mut pivot_rows = vec![];
+    for row in 0..rows {
+        let pivot = pivot_rows.len();
+        if pivot == columns {
+            break;
+        }
+
+        // fold every later entry of this row into the pivot column
+        for j in pivot + 1..columns {
+            let (a, b) = (reduced[row][pivot], reduced[row][j]);
+            if b == 0 {
+                continue;
+            }
+            let (g, s, t) = extended_gcd(a, b);
+            // determinant s * a / g + t * b / g = 1
+            let operation = (s, t, -b / g, a / g);
+            combine(&mut reduced, pivot, j, operation);
+            combine(&mut unimodular, pivot, j, operation);
+        }
+
+        if reduced[row][pivot] != 0 {
+            pivot_rows.push(row);
+        }
+    }
+
+    // forward substitution through the column echelon form
+    let rank = pivot_rows.len();
+    let mut y = vec![0; rank];
+    for (i, &row) in pivot_rows.iter().enumerate() {
+        let rest = target[row] - (0..i).map(|j| reduced[row][j] * y[j]).sum::<i128>();
+        if rest % reduced[row][i] != 0 {
+            return None;
+        }
+        y[i] = rest / reduced[row][i];
+    }
+
+    let particular: Vec<i128> = (0..columns)
+        .map(|i| (0..rank).map(|j| unimodular[i][j] * y[j]).sum())
+        .collect();
+
+    // rows without a pivot only hold if the system is consistent
+    let consistent = matrix.iter().zip(target).all(|(row, &value)| {
+        row.iter()
+            .zip(&particular)
+            .map(|(a, n)| a * n)
+            .sum::<i128>()
+            == value
+    });
+    if !consistent {
+        return None;
+    }
+
+    let kernel = (rank..columns)
+        .map(|j| (0..columns).map(|i| unimodular[i][j]).collect())
+        .collect();
+
+    Some((particular, kernel))
+}
+
+/// `coefficients · t >= bound`
+#[derive(Debug, Clone)]
+struct Constraint {
+    coefficients: Vec<i128>,
+    bound: i128,
+}
+
+/// Keeps the cheapest integer point found so far in `best`, as `(cost, t)`.
+fn branch_and_bound(
+    constraints: Vec<Constraint>,
+    objective: &[i128],
+    best: &mut Option<(i128, Vec<i128>)>,
+) {
+    let Some((value, vertex)) = lp_minimum(&constraints, objective) else {
+        return;
+    };
+    if best
+        .as_ref()
+        .is_some_and(|&(best_value, _)| value >= Fraction::from(best_value))
+    {
+        return;
+    }
+
+    match vertex.iter().position(|x| !x.is_integer()) {
+        None => *best = Some((value.floor(), vertex.iter().map(|x| x.floor()).collect())),
+        Some(i) => {
+            let unit = |sign: i128| -> Vec<i128> {
+                (0..objective.len())
+                    .map(|j| if i == j { sign } else { 0 })
+                    .collect()
+            };
+
+            // t_i <= floor(x) or t_i >= ceil(x)
+            let mut below = constraints.clone();
+            below.push(Constraint {
+                coefficients: unit(-1),
+                bound: -vertex[i].floor(),
+            });
+            branch_and_bound(below, objective, best);
+
+            let mut above = constraints;
+            above.push(Constraint {
+                coefficients: unit(1),
+                bound: vertex[i].ceil(),
+            });
+            branch_and_bound(above, objective, best);
+        }
+    }
+}
+
+/// Minimizes `objective · t` over the rational polytope given by `constraints`
+/// by trying every vertex, i.e. every choice of as many tight constraints as
+/// there are dimensions.
+fn lp_minimum(constraints: &[Constraint], objective: &[i128]) -> Option<(Fraction, Vec<Fraction>)> {
+    let dimensions = objective.len();
+    let mut best: Option<(Fraction, Vec<Fraction>)> = None;
+
+    for subset in combinations(constraints.len(), dimensions) {
+        let Some(vertex) = solve_tight(constraints, &subset) else {
+            continue;
+        };
+
+        let feasible = constraints.iter().all(|constraint| {
+            dot(&constraint.coefficients, &vertex) >= Fraction::from(constraint.bound)
+        });
+        if !feasible {
+            continue;
+        }
+
+        let value = dot(objective, &vertex);
+        if best
+            .as_ref()
+            .is_none_or(|(best_value, _)| value < *best_value)
+        {
+            best = Some((value, vertex));
+        }
+    }
+
+    best
+}
+
+/// Solves the square system of the chosen constraints holding with equality.
+fn solve_tight(constraints: &[Constraint], subset: &[usize]) -> Option<Vec<Fraction>> {
+    let n = subset.len();
+    let mut rows: Vec<Vec<Fraction>> = subset
+        .iter()
+        .map(|&i| {
+            let constraint = &constraints[i];
+            constraint
+                .coefficients
+                .iter()
+                .chain([&constraint.bound])
+                .map(|&x| Fraction::from(x))
+                .collect()
+        })
+        .collect();
+
+    for column in 0..n {
+        let pivot = (column..n).find(|&row| rows[row][column] != Fraction::from(0))?;
+        rows.swap(column, pivot);
+
+        let pivot_row = rows[column].clone();
+        for (i, row) in rows.iter_mut().enumerate() {
+            if i != column {
+                let factor = row[column] / pivot_row[column];
+                for (x, &p) in row.iter_mut().zip(&pivot_row).skip(column) {
+                    *x = *x - factor * p;
+                }
+            }
+        }
+    }
+
+    Some((0..n).map(|i| rows[i][n] / rows[i][i]).collect())
+}
+
+fn dot(coefficients: &[i128], point: &[Fraction]) -> Fraction {
+    coefficients
+        .iter()
+        .zip(point)
+        .fold(Fraction::from(0), |sum, (&c, &x)| {
+            sum + Fraction::from(c) * x
+        })
+}
+
+/// All `k`-element subsets of `0..n`, in lexicographic order.
+fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    let mut result = vec![];
+    let mut current = Vec::with_capacity(k);
+
+    fn extend(
+        start: usize,
+        n: usize,
+        k: usize,
+        current: &mut Vec<usize>,
+        result: &mut Vec<Vec<usize>>,
+    ) {
+        if current.len() == k {
+            result.push(current.clone());
+            return;
+        }
+        for i in start..n {
+            current.push(i);
+            extend(i + 1, n, k, current, result);
+            current.pop();
+        }
+    }
+
+    extend(0, n, k, &mut current, &mut result);
+    result
+}
+
+/// Returns `(g, s, t)` with `a * s + b * t = g = gcd(a, b)` and `g >= 0`.
+pub fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a.abs(), a.signum(), 0)
+    } else {
+        let (g, s, t) = extended_gcd(b, a.rem_euclid(b));
+        (g, t, s - a.div_euclid(b) * t)
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    extended_gcd(a, b).0
+}
+
+/// Exact rational number, kept in lowest terms with a positive denominator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fraction {
+    numerator: i128,
+    denominator: i128,
+}
+
+impl Fraction {
+    fn new(numerator: i128, denominator: i128) -> Self {
+        assert!(denominator != 0, "Division by zero");
+        let divisor = gcd(numerator, denominator) * denominator.signum();
+        Fraction {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        }
+    }
+
+    fn is_integer(self) -> bool {
+        self.denominator == 1
+    }
+
+    fn floor(self) -> i128 {
+        self.numerator.div_euclid(self.denominator)
+    }
+
+    fn ceil(self) -> i128 {
+        -(-self.numerator).div_euclid(self.denominator)
+    }
+}
+
+impl From<i128> for Fraction {
+    fn from(value: i128) -> Self {
+        Fraction {
+            numerator: value,
+            denominator: 1,
+        }
+    }
+}
+
+impl Add for Fraction {
+    type Output = Fraction;
+
+    fn add(self, other: Fraction) -> Fraction {
+        Fraction::new(
+            self.numerator * other.denominator + other.numerator * self.denominator,
+            self.denominator * other.denominator,
+        )
+    }
+}
+
+impl Sub for Fraction {
+    type Output = Fraction;
+
+    fn sub(self, other: Fraction) -> Fraction {
+        self + Fraction::new(-other.numerator, other.denominator)
+    }
+}
+
+impl Mul for Fraction {
+    type Output = Fraction;
+
+    fn mul(self, other: Fraction) -> Fraction {
+        Fraction::new(
+            self.numerator * other.numerator,
+            self.denominator * other.denominator,
+        )
+    }
+}
+
+impl Div for Fraction {
+    type Output = Fraction;
+
+    fn div(self, other: Fraction) -> Fraction {
+        Fraction::new(
+            self.numerator * other.denominator,
+            self.denominator * other.numerator,
+        )
+    }
+}
+
+impl PartialOrd for Fraction {
+    fn partial_cmp(&self, other: &Fraction) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Fraction {
+    fn cmp(&self, other: &Fraction) -> Ordering {
+        (self.numerator * other.denominator).cmp(&(other.numerator * self.denominator))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve_lattice() {
+        // x + 2y = 5
+        let (particular, kernel) = solve_lattice(&[vec![1, 2]], &[5]).unwrap();
+        assert_eq!(particular[0] + 2 * particular[1], 5);
+        assert_eq!(kernel.len(), 1);
+        assert_eq!(kernel[0][0] + 2 * kernel[0][1], 0);
+
+        assert!(solve_lattice(&[vec![2, 4]], &[5]).is_none());
+        assert!(solve_lattice(&[vec![1, 2], vec![1, 2]], &[5, 6]).is_none());
+    }
+
+    #[test]
+    fn test_minimize() {
+        // coins of 1, 5 and 10, where the 10 is cheaper than two 5s
+        let matrix = [vec![1, 5, 10]];
+        assert_eq!(
+            minimize(&matrix, &[27], &[1, 2, 3], None),
+            Some(vec![2, 1, 2])
+        );
+        assert_eq!(
+            minimize(&matrix, &[27], &[1, 2, 5], None),
+            Some(vec![2, 5, 0])
+        );
+
+        // limited presses force the expensive option
+        assert_eq!(
+            minimize(&matrix, &[27], &[1, 2, 5], Some(3)),
+            Some(vec![2, 3, 1])
+        );
+        assert_eq!(
+            minimize(&matrix, &[27], &[1, 2, 5], Some(2)),
+            Some(vec![2, 1, 2])
+        );
+        assert_eq!(minimize(&matrix, &[27], &[1, 2, 5], Some(1)), None);
+    }
+
+    #[test]
+    fn test_minimize_3d() {
+        let matrix = [vec![1, 0, 1, 2], vec![0, 1, 1, 1], vec![1, 1, 2, 3]];
+        let solution = minimize(&matrix, &[7, 5, 12], &[2, 1, 2, 1], None).unwrap();
+
+        assert_eq!(solution, vec![0, 1, 1, 3]);
+    }
+}
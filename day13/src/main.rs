@@ -1,70 +1,142 @@
+mod lattice;
+
 use regex::Regex;
+use std::env;
 use std::error::Error;
 use std::fs;
 
 const OFFSET: i128 = 10_000_000_000_000;
 
+/// Token costs of buttons without an explicit `Cost:`
+const TOKENS_A: i128 = 3;
+const TOKENS_OTHER: i128 = 1;
+
+/// Button presses allowed per button in part 1
+const MAX_PRESSES: i128 = 100;
+
+#[derive(Debug, Clone)]
+struct Button {
+    name: String,
+    /// Movement along each axis of the machine, in prize order
+    moves: Vec<i128>,
+    cost: i128,
+}
+
 #[derive(Debug, Clone)]
 struct ClawMachine {
-    a_1: i128,
-    a_2: i128,
-    b_1: i128,
-    b_2: i128,
-    c_1: i128,
-    c_2: i128,
+    buttons: Vec<Button>,
+    prize: Vec<i128>,
 }
 
+/// Parses machines separated by blank lines, such as
+///
+/// ```text
+/// Button A: X+94, Y+34
+/// Button B: X+22, Y+67
+/// Button C: X+5, Y+5, Cost: 2
+/// Prize: X=8400, Y=5400
+/// ```
+///
+/// The prize line names the axes; buttons may leave out axes they don't move.
 fn parse_machines(input: &str) -> Result<Vec<ClawMachine>, Box<dyn Error>> {
-    let machine_regex = Regex::new(
-        r"Button A: X\+(\d+), Y\+(\d+)\r?\nButton B: X\+(\d+), Y\+(\d+)\r?\nPrize: X=(\d+), Y=(\d+)",
-    )?;
-
-    let machines: Vec<ClawMachine> = machine_regex
-        .captures_iter(input)
-        .map(|cap| {
-            Ok(ClawMachine {
-                a_1: cap[1].parse()?,
-                a_2: cap[2].parse()?,
-                b_1: cap[3].parse()?,
-                b_2: cap[4].parse()?,
-                c_1: cap[5].parse()?,
-                c_2: cap[6].parse()?,
-            })
-        })
-        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    let button_regex = Regex::new(r"^Button (\w+): (.*)$")?;
+    let move_regex = Regex::new(r"([A-Z])([+-]\d+)")?;
+    let cost_regex = Regex::new(r"Cost: (\d+)")?;
+    let prize_regex = Regex::new(r"([A-Z])=(-?\d+)")?;
 
-    Ok(machines)
-}
+    let mut machines = vec![];
+    for block in input.replace('\r', "").split("\n\n") {
+        let lines: Vec<&str> = block
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        let Some((prize_line, button_lines)) = lines.split_last() else {
+            continue;
+        };
+
+        let prize_line = prize_line
+            .strip_prefix("Prize: ")
+            .ok_or_else(|| format!("Expected a prize line, got {prize_line:?}"))?;
+        let (axes, prize): (Vec<&str>, Vec<i128>) = prize_regex
+            .captures_iter(prize_line)
+            .map(|cap| Ok((cap.get(1).unwrap().as_str(), cap[2].parse::<i128>()?)))
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?
+            .into_iter()
+            .unzip();
+
+        let mut buttons = vec![];
+        for line in button_lines {
+            let cap = button_regex
+                .captures(line)
+                .ok_or_else(|| format!("Expected a button line, got {line:?}"))?;
+            let name = cap[1].to_string();
 
-fn solve_equation(machine: &ClawMachine, offset: i128) -> Option<(i128, i128, i128)> {
-    let (a_1, a_2) = (machine.a_1, machine.a_2);
-    let (b_1, b_2) = (machine.b_1, machine.b_2);
-    let (c_1, c_2) = (machine.c_1, machine.c_2);
+            let mut moves = vec![0; axes.len()];
+            for movement in move_regex.captures_iter(&cap[2]) {
+                let axis = axes
+                    .iter()
+                    .position(|&axis| axis == &movement[1])
+                    .ok_or_else(|| {
+                        format!("Button {name} moves along unknown axis {}", &movement[1])
+                    })?;
+                moves[axis] = movement[2].parse()?;
+            }
 
-    let denominator = a_1 * b_2 - b_1 * a_2;
-    if denominator == 0 {
-        return None; // No solution if the determinant is zero
+            let cost = match cost_regex.captures(&cap[2]) {
+                Some(cost) => cost[1].parse()?,
+                None if name == "A" => TOKENS_A,
+                None => TOKENS_OTHER,
+            };
+            if cost <= 0 {
+                return Err(format!("Button {name} must cost at least one token").into());
+            }
+
+            buttons.push(Button { name, moves, cost });
+        }
+
+        machines.push(ClawMachine { buttons, prize });
     }
 
-    let x = (c_1 * b_2 - b_1 * c_2 + (b_2 - b_1) * offset) / denominator;
-    let y = (a_1 * c_2 - c_1 * a_2 + (a_1 - a_2) * offset) / denominator;
-    let tokens = 3 * x + y;
+    Ok(machines)
+}
+
+/// Finds the cheapest non-negative number of presses per button reaching the
+/// machine's prize moved by `offset` on every axis, returning the presses and
+/// the tokens spent.
+///
+/// The presses solve `moves · n = prize`, an integer program handed to
+/// [`lattice::minimize`]. With independent buttons there is at most one
+/// solution; otherwise the solver searches the whole solution lattice.
+fn solve_equation(
+    machine: &ClawMachine,
+    offset: i128,
+    max_presses: Option<i128>,
+) -> Option<(Vec<i128>, i128)> {
+    let matrix: Vec<Vec<i128>> = (0..machine.prize.len())
+        .map(|axis| {
+            machine
+                .buttons
+                .iter()
+                .map(|button| button.moves[axis])
+                .collect()
+        })
+        .collect();
+    let target: Vec<i128> = machine.prize.iter().map(|c| c + offset).collect();
+    let costs: Vec<i128> = machine.buttons.iter().map(|button| button.cost).collect();
 
-    Some((x, y, tokens))
+    let presses = lattice::minimize(&matrix, &target, &costs, max_presses)?;
+    let tokens = presses.iter().zip(&costs).map(|(n, cost)| n * cost).sum();
+
+    Some((presses, tokens))
 }
 
 fn solve_claw_machines(machines: &[ClawMachine], offset: i128) -> i128 {
+    let max_presses = (offset == 0).then_some(MAX_PRESSES);
+
     machines
         .iter()
-        .filter_map(|machine| solve_equation(machine, offset))
-        .filter(|&(x, y, _)| x >= 0 && y >= 0 && (offset != 0 || (x <= 100 && y <= 100)))
-        .filter(|&(x, y, _)| {
-            machines.iter().any(|m| {
-                x * m.a_1 + y * m.b_1 == m.c_1 + offset &&
-                x * m.a_2 + y * m.b_2 == m.c_2 + offset
-            })
-        })
-        .map(|(_, _, tokens)| tokens)
+        .filter_map(|machine| solve_equation(machine, offset, max_presses))
+        .map(|(_, tokens)| tokens)
         .sum()
 }
 
@@ -83,5 +155,126 @@ fn main() -> Result<(), Box<dyn Error>> {
     let part2_tokens = solve_claw_machines(&machines, OFFSET);
     println!("Part 2: {}", part2_tokens);
 
+    // Optionally solve machines with more buttons or axes, without limits
+    if let Some(path) = env::args().nth(1) {
+        let input = fs::read_to_string(path)?;
+        for (i, machine) in parse_machines(&input)?.iter().enumerate() {
+            match solve_equation(machine, 0, None) {
+                Some((presses, tokens)) => {
+                    let presses: Vec<String> = machine
+                        .buttons
+                        .iter()
+                        .zip(&presses)
+                        .map(|(button, n)| format!("{}×{}", button.name, n))
+                        .collect();
+                    println!(
+                        "Machine {}: {} tokens ({})",
+                        i + 1,
+                        tokens,
+                        presses.join(", ")
+                    );
+                }
+                None => println!("Machine {}: unreachable", i + 1),
+            }
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(a: (i128, i128), b: (i128, i128), c: (i128, i128)) -> ClawMachine {
+        let input = format!(
+            "Button A: X{:+}, Y{:+}\nButton B: X{:+}, Y{:+}\nPrize: X={}, Y={}",
+            a.0, a.1, b.0, b.1, c.0, c.1
+        );
+        parse_machines(&input).unwrap().remove(0)
+    }
+
+    #[test]
+    fn test_solve_claw_machines() {
+        let input = fs::read_to_string("test.txt").expect("Failed to read test file");
+        let machines = parse_machines(&input).unwrap();
+
+        assert_eq!(solve_claw_machines(&machines, 0), 480);
+        assert_eq!(solve_claw_machines(&machines, OFFSET), 875318608908);
+    }
+
+    #[test]
+    fn test_solve_equation() {
+        let m = machine((94, 34), (22, 67), (8400, 5400));
+        assert_eq!(
+            solve_equation(&m, 0, Some(MAX_PRESSES)),
+            Some((vec![80, 40], 280))
+        );
+
+        // no integer solution
+        let m = machine((26, 66), (67, 21), (12748, 12176));
+        assert_eq!(solve_equation(&m, 0, None), None);
+    }
+
+    #[test]
+    fn test_collinear() {
+        // x + 2y = 5 has (5, 0), (3, 1) and (1, 2), the last being cheapest
+        let m = machine((1, 1), (2, 2), (5, 5));
+        assert_eq!(solve_equation(&m, 0, None), Some((vec![1, 2], 5)));
+
+        // B is cheaper per unit of distance, so only press B
+        let m = machine((3, 6), (1, 2), (7, 14));
+        assert_eq!(solve_equation(&m, 0, None), Some((vec![0, 7], 7)));
+        assert_eq!(solve_equation(&m, 0, Some(5)), Some((vec![1, 4], 7)));
+        assert_eq!(solve_equation(&m, 0, Some(2)), Some((vec![2, 1], 7)));
+        assert_eq!(solve_equation(&m, 0, Some(1)), None);
+
+        // A pays off once it covers more than three times B's distance
+        let m = machine((10, 0), (3, 0), (23, 0));
+        assert_eq!(solve_equation(&m, 0, None), Some((vec![2, 1], 7)));
+
+        // prize off the line, or not reachable in whole steps
+        assert_eq!(
+            solve_equation(&machine((1, 1), (2, 2), (5, 6)), 0, None),
+            None
+        );
+        assert_eq!(
+            solve_equation(&machine((2, 2), (4, 4), (5, 5)), 0, None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_more_buttons() {
+        let input = "\
+Button A: X+94, Y+34
+Button B: X+22, Y+67
+Button C: X+1, Y+1, Cost: 1
+Prize: X=8400, Y=5400
+
+Button A: X+1, Y+0, Z+0
+Button B: X+0, Y+1, Z+1
+Button C: X+1, Y+1, Z+1, Cost: 1
+Button D: Z+1, Cost: 5
+Prize: X=3, Y=4, Z=6
+";
+        let machines = parse_machines(input).unwrap();
+        assert_eq!(machines[0].buttons.len(), 3);
+        assert_eq!(machines[1].buttons[3].moves, vec![0, 0, 1]);
+
+        // C never pays off against the cheaper combination of A and B
+        assert_eq!(
+            solve_equation(&machines[0], 0, None),
+            Some((vec![80, 40, 0], 280))
+        );
+
+        // C covers X, B the rest of Y and the missing Z needs D
+        assert_eq!(
+            solve_equation(&machines[1], 0, None),
+            Some((vec![0, 1, 3, 2], 14))
+        );
+
+        assert!(parse_machines("Button A: W+1\nPrize: X=1").is_err());
+        assert!(parse_machines("Button A: X+1, Cost: 0\nPrize: X=1").is_err());
+    }
+}
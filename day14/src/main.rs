@@ -1,5 +1,5 @@
 use regex::Regex;
-use std::{collections::HashSet, fs};
+use std::{env, fs};
 
 const MAX_X: i32 = 101;
 const MAX_Y: i32 = 103;
@@ -20,47 +20,78 @@ struct Map {
 }
 
 impl Map {
-    // Simulate one step of movement for all robots
-    fn step(&mut self) {
-        for robot in &mut self.robots {
-            robot.position.0 = (robot.position.0 + robot.velocity.0).rem_euclid(self.max_x);
-            robot.position.1 = (robot.position.1 + robot.velocity.1).rem_euclid(self.max_y);
+    // Build a map from the robots in `input`. The arena size is taken from
+    // `size` if given, else from a `size=W,H` line in the input, else the
+    // puzzle's 101 by 103. Fails if the arena has no cells.
+    fn parse(input: &str, size: Option<(i32, i32)>) -> Result<Map, Box<dyn std::error::Error>> {
+        let re = Regex::new(r"^size=(\d+),(\d+)$").unwrap();
+        let (max_x, max_y) = match size {
+            Some(size) => size,
+            None => match input.lines().find_map(|line| re.captures(line.trim())) {
+                Some(captures) => (captures[1].parse()?, captures[2].parse()?),
+                None => (MAX_X, MAX_Y),
+            },
+        };
+        if max_x <= 0 || max_y <= 0 {
+            return Err(format!("Arena {max_x}x{max_y} must not be empty").into());
         }
+
+        Ok(Map {
+            max_x,
+            max_y,
+            robots: parse_robots(input),
+        })
+    }
+
+    // Position of every robot after `t` steps, wrapping around the arena
+    fn positions_at(&self, t: i64) -> Vec<(i32, i32)> {
+        let wrap = |position: i32, velocity: i32, size: i32| {
+            let size = size as i64;
+            let steps = t.rem_euclid(size);
+            (position as i64 + velocity as i64 * steps).rem_euclid(size) as i32
+        };
+
+        self.robots
+            .iter()
+            .map(|robot| {
+                (
+                    wrap(robot.position.0, robot.velocity.0, self.max_x),
+                    wrap(robot.position.1, robot.velocity.1, self.max_y),
+                )
+            })
+            .collect()
+    }
+
+    // The column and row through the middle of the arena
+    fn middle(&self) -> (i32, i32) {
+        (self.max_x / 2, self.max_y / 2)
+    }
+
+    // Count the robots in each quadrant after `t` steps, in the order top-left,
+    // top-right, bottom-left, bottom-right. Robots on the split column or row
+    // belong to no quadrant.
+    fn quadrant_counts(&self, t: i64, split: (i32, i32)) -> [usize; 4] {
+        let mut counts = [0; 4];
+        for (x, y) in self.positions_at(t) {
+            if x == split.0 || y == split.1 {
+                continue;
+            }
+            counts[(x > split.0) as usize + 2 * (y > split.1) as usize] += 1;
+        }
+        counts
     }
 
     // Calculate the safety factor based on robot positions in quadrants
-    fn safety_factor(&self) -> usize {
-        let quadrant_counts = [
-            // Top-left quadrant
-            self.robots
-                .iter()
-                .filter(|robot| robot.position.0 < self.max_x / 2 && robot.position.1 < self.max_y / 2)
-                .count(),
-            // Top-right quadrant
-            self.robots
-                .iter()
-                .filter(|robot| robot.position.0 > self.max_x / 2 && robot.position.1 < self.max_y / 2)
-                .count(),
-            // Bottom-left quadrant
-            self.robots
-                .iter()
-                .filter(|robot| robot.position.0 < self.max_x / 2 && robot.position.1 > self.max_y / 2)
-                .count(),
-            // Bottom-right quadrant
-            self.robots
-                .iter()
-                .filter(|robot| robot.position.0 > self.max_x / 2 && robot.position.1 > self.max_y / 2)
-                .count(),
-        ];
-
-        quadrant_counts.into_iter().product()
-    }
-
-    // Print the map to visualize robot positions
-    fn _print(&self) {
+    fn safety_factor(&self, t: i64, split: (i32, i32)) -> usize {
+        self.quadrant_counts(t, split).into_iter().product()
+    }
+
+    // Print the map to visualize robot positions after `t` steps
+    fn _print(&self, t: i64) {
+        let positions = self.positions_at(t);
         for y in 0..self.max_y {
             for x in 0..self.max_x {
-                if self.robots.iter().any(|robot| robot.position == (x, y)) {
+                if positions.contains(&(x, y)) {
                     print!("#");
                 } else {
                     print!(".");
@@ -70,13 +101,108 @@ impl Map {
         }
     }
 
-    // Check if all robots are in unique positions
-    fn spread_out(&self) -> bool {
-        let unique_positions: HashSet<_> = self.robots.iter().map(|robot| robot.position).collect();
-        unique_positions.len() == self.robots.len()
+    // Variance of the robots' coordinates along one axis after `t` steps
+    fn variance_at(&self, t: i64, axis: Axis) -> f64 {
+        let values: Vec<f64> = self
+            .positions_at(t)
+            .into_iter()
+            .map(|(x, y)| match axis {
+                Axis::X => x as f64,
+                Axis::Y => y as f64,
+            })
+            .collect();
+
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n
+    }
+
+    // Find the step, within one period of the axis, where the robots bunch up
+    // the most along it. Returns that step and how far its variance drops below
+    // the average over the period, from 0 (not at all) to 1 (a single line).
+    fn tightest_step(&self, axis: Axis) -> (i64, f64) {
+        let period = match axis {
+            Axis::X => self.max_x,
+            Axis::Y => self.max_y,
+        } as i64;
+
+        let variances: Vec<f64> = (0..period).map(|t| self.variance_at(t, axis)).collect();
+        let (step, minimum) = variances
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("Arena has no columns or rows");
+
+        let mean = variances.iter().sum::<f64>() / period as f64;
+        let confidence = if mean > 0.0 {
+            1.0 - minimum / mean
+        } else {
+            0.0
+        };
+
+        (step as i64, confidence)
+    }
+
+    // Detect the first step at which the robots form a picture.
+    //
+    // Each robot moves independently along x and y, so the x coordinates repeat
+    // every `max_x` steps and the y coordinates every `max_y` steps. The picture
+    // is where both are bunched up, which the Chinese remainder theorem turns
+    // into a single step. Returns `None` if the two minima never coincide.
+    fn detect_pattern(&self) -> Option<Detection> {
+        if self.robots.is_empty() {
+            return None;
+        }
+
+        let (step_x, confidence_x) = self.tightest_step(Axis::X);
+        let (step_y, confidence_y) = self.tightest_step(Axis::Y);
+        let step = crt(step_x, self.max_x as i64, step_y, self.max_y as i64)?;
+
+        Some(Detection {
+            step,
+            confidence: confidence_x.min(confidence_y),
+        })
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+enum Axis {
+    X,
+    Y,
+}
+
+// A detected picture: the step it appears at, and the weaker of the two axes'
+// confidence scores
+#[derive(Debug)]
+struct Detection {
+    step: i64,
+    confidence: f64,
+}
+
+// Returns `(g, s, t)` with `a * s + b * t = g = gcd(a, b)`
+fn extended_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, s, t) = extended_gcd(b, a % b);
+        (g, t, s - (a / b) * t)
+    }
+}
+
+// Smallest non-negative `t` with `t = a (mod m)` and `t = b (mod n)`, for
+// moduli that need not be coprime
+fn crt(a: i64, m: i64, b: i64, n: i64) -> Option<i64> {
+    let (g, s, _) = extended_gcd(m, n);
+    if (b - a) % g != 0 {
+        return None;
+    }
+
+    let lcm = m / g * n;
+    let k = ((b - a) / g * s).rem_euclid(n / g);
+    Some((a + m * k).rem_euclid(lcm))
+}
+
 // Parse robots from the input string
 fn parse_robots(input: &str) -> Vec<Robot> {
     let re = Regex::new(r"p=(-?\d+),(-?\d+) v=(-?\d+),(-?\d+)").unwrap();
@@ -98,33 +224,34 @@ fn parse_robots(input: &str) -> Vec<Robot> {
         .collect()
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Read the file contents
-    let file_contents = fs::read_to_string("input.txt")?;
+// Parse an arena size such as `11x7`
+fn parse_size(arg: &str) -> Result<(i32, i32), Box<dyn std::error::Error>> {
+    let (width, height) = arg.split_once('x').ok_or("Expected WIDTHxHEIGHT")?;
+    Ok((width.parse()?, height.parse()?))
+}
 
-    // Parse the robots from the input
-    let robots = parse_robots(&file_contents);
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Usage: day14 [input file] [WIDTHxHEIGHT]
+    let args: Vec<String> = env::args().collect();
+    let path = args.get(1).map_or("input.txt", String::as_str);
+    let size = args.get(2).map(|arg| parse_size(arg)).transpose()?;
 
-    // Initialize the map
-    let mut map = Map {
-        max_x: MAX_X,
-        max_y: MAX_Y,
-        robots,
-    };
+    // Read the file contents
+    let file_contents = fs::read_to_string(path)?;
 
-    for i in 1..10000 {
-        map.step();
+    // Parse the robots and the arena from the input
+    let map = Map::parse(&file_contents, size)?;
 
-        // Part 1: Calculate safety factor after 100 steps
-        if i == 100 {
-            println!("Part 1: {}", map.safety_factor());
-        }
+    // Part 1: Calculate safety factor after 100 steps
+    println!("Part 1: {}", map.safety_factor(100, map.middle()));
 
-        // Part 2: Determine when robots spread out
-        if map.spread_out() {
-            println!("Part 2: {}", i);
-            break;
-        }
+    // Part 2: Find the picture from the statistics of a single period
+    match map.detect_pattern() {
+        Some(detection) => println!(
+            "Part 2: {} (confidence {:.2})",
+            detection.step, detection.confidence
+        ),
+        None => println!("Part 2: no picture found"),
     }
 
     Ok(())
@@ -140,22 +267,75 @@ mod tests {
         // Read the test file contents
         let file_contents = fs::read_to_string("test.txt").unwrap();
 
-        // Parse the robots from the test input
-        let robots = parse_robots(&file_contents);
+        // Parse the robots on the example's smaller arena
+        let map = Map::parse(&file_contents, Some((11, 7))).unwrap();
 
-        // Initialize the map
-        let mut map = Map {
+        // Assert that the safety factor matches the expected value
+        assert_eq!(map.safety_factor(100, map.middle()), 12);
+    }
+
+    #[test]
+    fn test_positions_at() {
+        let input = "size=11,7\np=2,4 v=2,-3\n";
+        let map = Map::parse(input, None).unwrap();
+        assert_eq!((map.max_x, map.max_y), (11, 7));
+
+        assert_eq!(map.positions_at(0), vec![(2, 4)]);
+        assert_eq!(map.positions_at(1), vec![(4, 1)]);
+        assert_eq!(map.positions_at(5), vec![(1, 3)]);
+        assert_eq!(map.positions_at(77 * 1_000_000_000_000 + 5), vec![(1, 3)]);
+        assert_eq!(map.positions_at(-1), vec![(0, 0)]);
+
+        // robots on the split column or row are left out, wherever the split is
+        let input = "p=0,0 v=0,0\np=1,1 v=0,0\np=5,1 v=0,0\np=1,5 v=0,0\np=5,5 v=0,0\n";
+        let map = Map::parse(input, Some((11, 7))).unwrap();
+        assert_eq!(map.quadrant_counts(0, map.middle()), [2, 0, 1, 0]);
+        assert_eq!(map.quadrant_counts(0, (0, 0)), [0, 0, 0, 4]);
+        assert_eq!(map.quadrant_counts(0, (2, 3)), [2, 1, 1, 1]);
+        assert_eq!(map.safety_factor(0, (2, 3)), 2);
+    }
+
+    #[test]
+    fn test_detect_pattern() {
+        // robots that all meet at (3, 2) after 20 steps
+        let velocities: [(i32, i32); 7] =
+            [(1, 2), (-2, 3), (4, -1), (5, 5), (-3, -4), (2, 0), (0, 1)];
+        let robots = velocities
+            .iter()
+            .map(|&(vx, vy)| Robot {
+                position: ((3 - vx * 20).rem_euclid(11), (2 - vy * 20).rem_euclid(7)),
+                velocity: (vx, vy),
+            })
+            .collect();
+        let map = Map {
             max_x: 11,
             max_y: 7,
             robots,
         };
 
-        // Simulate 100 steps
-        for _ in 0..100 {
-            map.step();
-        }
+        let detection = map.detect_pattern().unwrap();
+        assert_eq!(detection.step, 20);
+        assert_eq!(detection.confidence, 1.0);
+    }
 
-        // Assert that the safety factor matches the expected value
-        assert_eq!(map.safety_factor(), 12);
+    #[test]
+    fn test_empty_arena() {
+        let input = "p=0,0 v=1,1\n";
+        assert!(Map::parse(input, Some((0, 7))).is_err());
+        assert!(Map::parse(input, parse_size("0x0").ok()).is_err());
+        assert!(Map::parse(&format!("size=11,0\n{input}"), None).is_err());
+        assert!(Map::parse("size=99999999999,7\n", None).is_err());
+
+        // an empty file still gets the puzzle's arena
+        let map = Map::parse("", None).unwrap();
+        assert_eq!((map.max_x, map.max_y), (MAX_X, MAX_Y));
+        assert!(map.detect_pattern().is_none());
+    }
+
+    #[test]
+    fn test_crt() {
+        assert_eq!(crt(9, 11, 6, 7), Some(20));
+        assert_eq!(crt(2, 4, 4, 6), Some(10));
+        assert_eq!(crt(1, 4, 2, 6), None);
     }
 }
@@ -0,0 +1,217 @@
+use std::fmt;
+
+use crate::warehouse::{Move, Position, Rect, Warehouse};
+
+/// One input move and what it did, if anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub direction: char,
+    /// `None` for a blocked move
+    pub change: Option<Move>,
+    /// Where the robot stood before the move
+    pub robot: Position,
+    /// Index and rectangle before the move of every pushed box, by index
+    pub boxes: Vec<(usize, Rect)>,
+}
+
+/// A warehouse together with the moves played on it, supporting undo, redo
+/// and replaying from the start.
+pub struct Session {
+    initial: Warehouse,
+    pub warehouse: Warehouse,
+    log: Vec<Step>,
+    redo: Vec<Step>,
+}
+
+impl Session {
+    pub fn new(warehouse: Warehouse) -> Self {
+        Session {
+            initial: warehouse.clone(),
+            warehouse,
+            log: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+
+    /// Number of steps played and not undone
+    pub fn steps(&self) -> usize {
+        self.log.len()
+    }
+
+    /// The directions played so far, blocked ones included.
+    pub fn moves(&self) -> String {
+        self.log.iter().map(|step| step.direction).collect()
+    }
+
+    /// Plays a move, discarding anything that could have been redone. Returns
+    /// whether the robot moved.
+    pub fn play(&mut self, direction: char) -> bool {
+        let step = self.record(direction);
+        let moved = step.change.is_some();
+
+        self.log.push(step);
+        self.redo.clear();
+        moved
+    }
+
+    /// Moves the robot, noting where it and every box it pushes started.
+    fn record(&mut self, direction: char) -> Step {
+        let robot = self.warehouse.robot;
+        let change = self.warehouse.move_robot(direction);
+
+        let mut boxes: Vec<_> = match &change {
+            Some(change) => {
+                let back = Position {
+                    x: -change.delta.x,
+                    y: -change.delta.y,
+                };
+                change
+                    .pushed
+                    .iter()
+                    .map(|&i| {
+                        let rect = self.warehouse.boxes[i];
+                        (
+                            i,
+                            Rect {
+                                origin: rect.origin.offset(back),
+                                ..rect
+                            },
+                        )
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        boxes.sort();
+
+        Step {
+            direction,
+            change,
+            robot,
+            boxes,
+        }
+    }
+
+    /// Takes back the latest step, returning `false` if there is none.
+    pub fn undo(&mut self) -> bool {
+        let Some(step) = self.log.pop() else {
+            return false;
+        };
+
+        if let Some(change) = &step.change {
+            self.warehouse.undo_move(change);
+        }
+        self.redo.push(step);
+        true
+    }
+
+    /// Plays the most recently undone step again, returning `false` if there
+    /// is none.
+    pub fn redo(&mut self) -> bool {
+        let Some(step) = self.redo.pop() else {
+            return false;
+        };
+
+        let redone = self.record(step.direction);
+        debug_assert_eq!(redone, step, "Redo diverged from the log");
+        self.log.push(redone);
+        true
+    }
+
+    /// Rebuilds the state after the first `step` steps by playing them again
+    /// on the initial warehouse.
+    pub fn replay(&self, step: usize) -> Warehouse {
+        let mut warehouse = self.initial.clone();
+        for logged in &self.log[..step] {
+            warehouse.move_robot(logged.direction);
+        }
+        warehouse
+    }
+
+    /// What changed during step `step`, counting from 1, or `None` if there
+    /// is no such step.
+    pub fn diff(&self, step: usize) -> Option<StepDiff> {
+        let logged = self.log.get(step.checked_sub(1)?)?;
+        let robot = logged.robot;
+
+        let (robot, boxes) = match &logged.change {
+            Some(change) => {
+                let shift = |rect: Rect| Rect {
+                    origin: rect.origin.offset(change.delta),
+                    ..rect
+                };
+                let boxes = logged
+                    .boxes
+                    .iter()
+                    .map(|&(i, rect)| (i, rect, shift(rect)))
+                    .collect();
+
+                ((robot, robot.offset(change.delta)), boxes)
+            }
+            None => ((robot, robot), Vec::new()),
+        };
+
+        Some(StepDiff {
+            step,
+            direction: logged.direction,
+            robot,
+            boxes,
+        })
+    }
+}
+
+/// Returns the first step, counting from 1, at which two sessions played
+/// with the same moves behave differently: one is blocked where the other
+/// moves, or they push different boxes.
+///
+/// Boxes are numbered in map order whatever the scale, so the narrow and wide
+/// versions of a map can be compared directly.
+pub fn first_divergence(a: &Session, b: &Session) -> Option<usize> {
+    a.log
+        .iter()
+        .zip(&b.log)
+        .position(|(x, y)| {
+            let pushed = |step: &Step| {
+                let mut pushed = step.change.as_ref().map(|change| change.pushed.clone());
+                if let Some(pushed) = &mut pushed {
+                    pushed.sort();
+                }
+                pushed
+            };
+            x.direction != y.direction || pushed(x) != pushed(y)
+        })
+        .map(|i| i + 1)
+}
+
+/// The robot's and boxes' positions before and after one step.
+#[derive(Debug, PartialEq, Eq)]
+pub struct StepDiff {
+    pub step: usize,
+    pub direction: char,
+    pub robot: (Position, Position),
+    /// Box index with its old and new rectangle, for every pushed box
+    pub boxes: Vec<(usize, Rect, Rect)>,
+}
+
+impl fmt::Display for StepDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (from, to) = self.robot;
+        if from == to {
+            return writeln!(
+                f,
+                "step {} {}: blocked at {}",
+                self.step, self.direction, from
+            );
+        }
+
+        writeln!(
+            f,
+            "step {} {}: robot {} -> {}",
+            self.step, self.direction, from, to
+        )?;
+        for (i, old, new) in &self.boxes {
+            writeln!(f, "  box {} {} -> {}", i, old.origin, new.origin)?;
+        }
+        Ok(())
+    }
+}
@@ -1,15 +1,422 @@
-use std::fs;
+use std::io::{self, IsTerminal};
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::{env, fs};
 
-mod part1;
-mod part2;
+mod history;
+mod player;
+mod solver;
+mod warehouse;
+
+use history::{first_divergence, Session};
+use player::Player;
+#[cfg(unix)]
+use player::RawMode;
+use solver::{solve, Goal};
+use warehouse::{parse_input, solve_warehouse_puzzle, Warehouse};
+
+/// Usage: day15 play <map file> [--scale N] [--save FILE]
+///
+/// Reads keys from stdin, so a moves file can be piped in as well. Outside
+/// Unix the terminal stays line-buffered, so typed keys take effect on Enter
+/// and only the end is drawn.
+fn play(args: &[String]) -> io::Result<()> {
+    let mut path = None;
+    let mut scale = 1;
+    let mut save_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--scale" => {
+                scale = args
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .expect("--scale needs a positive number");
+            }
+            "--save" => save_path = Some(PathBuf::from(args.next().expect("--save needs a file"))),
+            _ => path = Some(arg),
+        }
+    }
+
+    let input = fs::read_to_string(path.expect("No map file given"))?;
+    let (map, _) = parse_input(&input);
+
+    let mut player = Player {
+        session: Session::new(Warehouse::new(&map, scale)),
+        save_path,
+        live: cfg!(unix) && io::stdin().is_terminal() && io::stdout().is_terminal(),
+    };
+    // piped input is read as it is
+    #[cfg(unix)]
+    let _raw_mode = if player.live {
+        Some(RawMode::enable(io::stdin().as_raw_fd())?)
+    } else {
+        None
+    };
+    player.run(io::stdin().lock(), &mut io::stdout().lock())
+}
+
+/// Usage: day15 solve <map file> (<goal map file> | --gps N) [--scale N] [--limit N]
+///
+/// The goal map shows where the boxes should end up; walls and robot in it
+/// are ignored.
+fn find_moves(args: &[String]) -> io::Result<()> {
+    let mut paths = Vec::new();
+    let mut scale = 1;
+    let mut gps = None;
+    let mut limit = 1_000_000;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut number = |name: &str| {
+            args.next()
+                .and_then(|n| n.parse().ok())
+                .unwrap_or_else(|| panic!("{} needs a number", name))
+        };
+        match arg.as_str() {
+            "--scale" => scale = number("--scale") as i32,
+            "--gps" => gps = Some(number("--gps") as i32),
+            "--limit" => limit = number("--limit"),
+            _ => paths.push(arg),
+        }
+    }
+
+    let input = fs::read_to_string(paths.first().expect("No map file given"))?;
+    let warehouse = Warehouse::new(&parse_input(&input).0, scale);
+
+    let goal = match (gps, paths.get(1)) {
+        (Some(gps), _) => Goal::Gps(gps),
+        (None, Some(path)) => {
+            let goal = fs::read_to_string(path)?;
+            Goal::Boxes(Warehouse::new(&parse_input(&goal).0, scale).boxes)
+        }
+        (None, None) => panic!("Give a goal map or --gps"),
+    };
+
+    match solve(&warehouse, &goal, limit) {
+        Some(solution) => println!(
+            "{} moves ({} states explored):\n{}",
+            solution.moves.len(),
+            solution.explored,
+            solution.moves
+        ),
+        None => println!("No solution within {} states", limit),
+    }
+    Ok(())
+}
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("play") => {
+            play(&args[2..]).expect("Player failed");
+            return;
+        }
+        Some("solve") => {
+            find_moves(&args[2..]).expect("Solver failed");
+            return;
+        }
+        _ => {}
+    }
+
     // Read entire input file
     let input = fs::read_to_string("input.txt").expect("Could not read input file");
+    let (map, moves) = parse_input(&input);
+
+    let mut narrow = Session::new(Warehouse::new(&map, 1));
+    let mut wide = Session::new(Warehouse::new(&map, 2));
+    for &mv in &moves {
+        narrow.play(mv);
+        wide.play(mv);
+    }
+
+    let part1 = narrow.warehouse.calculate_gps_coordinates();
+    println!("Part 1: {}", part1);
+
+    let part2 = wide.warehouse.calculate_gps_coordinates();
+    println!("Part 2: {}", part2);
+
+    let result = solve_warehouse_puzzle(&input, 3);
+    println!("Triple width: {}", result);
+
+    // Show where the wide boxes first make a difference
+    if let Some(step) = first_divergence(&narrow, &wide) {
+        println!("Wide warehouse before step {}:", step);
+        print!("{}", wide.replay(step - 1));
+        println!("Narrow and wide first diverge at:");
+        print!("{}", narrow.diff(step).unwrap());
+        print!("{}", wide.diff(step).unwrap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warehouse::{Position, Rect};
+
+    #[test]
+    fn test_examples() {
+        let small = fs::read_to_string("test.txt").unwrap();
+        let large = fs::read_to_string("test2.txt").unwrap();
+        let wide = fs::read_to_string("test3.txt").unwrap();
+
+        assert_eq!(solve_warehouse_puzzle(&small, 1), 2028);
+        assert_eq!(solve_warehouse_puzzle(&large, 1), 10092);
+        assert_eq!(solve_warehouse_puzzle(&large, 2), 9021);
+        assert_eq!(solve_warehouse_puzzle(&wide, 2), 618);
+    }
+
+    #[test]
+    fn test_scaled_push() {
+        let (map, moves) = parse_input(&fs::read_to_string("test3.txt").unwrap());
+        let mut warehouse = Warehouse::new(&map, 3);
+        for mv in moves {
+            warehouse.move_robot(mv);
+        }
+
+        let expected = "\
+#####################
+###.......@.###...###
+###........[=]....###
+###....[=]........###
+###......[=]......###
+###...............###
+#####################
+";
+        assert_eq!(warehouse.to_string(), expected);
+    }
+
+    #[test]
+    fn test_mixed_widths() {
+        // a wide box pushes both narrow boxes resting on it
+        let map = "\
+#######
+#.....#
+#.OO..#
+#.[=].#
+#..@..#
+#######";
+        let mut warehouse = Warehouse::new(map, 1);
+
+        assert!(warehouse.move_robot('^').is_some());
+        assert_eq!(
+            warehouse.to_string(),
+            "#######\n#.OO..#\n#.[=].#\n#..@..#\n#.....#\n#######\n"
+        );
+
+        // the narrow boxes are against the wall, from either half of the wide one
+        assert!(warehouse.move_robot('^').is_none());
+        assert!(warehouse.move_robot('<').is_some());
+        assert!(warehouse.move_robot('^').is_none());
+
+        // around the side and push both narrow boxes right in one go
+        for mv in "<^^>".chars() {
+            assert!(warehouse.move_robot(mv).is_some());
+        }
+        assert_eq!(
+            warehouse.to_string(),
+            "#######\n#.@OO.#\n#.[=].#\n#.....#\n#.....#\n#######\n"
+        );
+        assert_eq!(warehouse.calculate_gps_coordinates(), 103 + 104 + 202);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let (map, moves) = parse_input(&fs::read_to_string("test2.txt").unwrap());
+        let mut session = Session::new(Warehouse::new(&map, 2));
+        for &mv in &moves {
+            session.play(mv);
+        }
+        assert_eq!(session.moves(), moves.iter().collect::<String>());
+
+        let end = session.warehouse.clone();
+        while session.undo() {}
+        assert_eq!(session.steps(), 0);
+        assert_eq!(session.warehouse, Warehouse::new(&map, 2));
+
+        for _ in 0..100 {
+            session.redo();
+        }
+        assert_eq!(session.warehouse, session.replay(100));
+
+        // playing a new move drops the remaining redo steps
+        session.play('<');
+        assert!(!session.redo());
+        session.undo();
+        while session.redo() {}
+        assert_eq!(session.steps(), 101);
+        assert!(session.moves().ends_with('<'));
+
+        // undoing half the moves lands on the replayed state, and redoing
+        // them on the final one
+        let mut session = Session::new(Warehouse::new(&map, 2));
+        for &mv in &moves {
+            session.play(mv);
+        }
+        let halfway = session.steps() / 2;
+        let replayed = session.replay(halfway);
+        while session.steps() > halfway {
+            session.undo();
+        }
+        assert_eq!(session.warehouse, replayed);
+        while session.redo() {}
+        assert_eq!(session.moves(), moves.iter().collect::<String>());
+        assert_eq!(session.warehouse, end);
+
+        let mut full = Session::new(Warehouse::new(&map, 2));
+        moves.iter().for_each(|&mv| {
+            full.play(mv);
+        });
+        assert_eq!(full.warehouse, end);
+        assert_eq!(full.warehouse.calculate_gps_coordinates(), 9021);
+    }
+
+    #[test]
+    fn test_diff() {
+        let (map, moves) = parse_input(&fs::read_to_string("test.txt").unwrap());
+        let mut narrow = Session::new(Warehouse::new(&map, 1));
+        let mut wide = Session::new(Warehouse::new(&map, 2));
+        for &mv in &moves {
+            narrow.play(mv);
+            wide.play(mv);
+        }
+
+        // a wall blocks the first move and the fourth pushes the first box
+        assert_eq!(
+            narrow.diff(1).unwrap().to_string(),
+            "step 1 <: blocked at (2, 2)\n"
+        );
+        assert_eq!(
+            narrow.diff(2).unwrap().to_string(),
+            "step 2 ^: robot (2, 2) -> (2, 1)\n"
+        );
+        assert_eq!(
+            narrow.diff(4).unwrap().to_string(),
+            "step 4 >: robot (2, 1) -> (3, 1)\n  box 0 (3, 1) -> (4, 1)\n"
+        );
+
+        let step = first_divergence(&narrow, &wide).unwrap();
+        assert_ne!(
+            narrow.diff(step).unwrap().boxes.len(),
+            wide.diff(step).unwrap().boxes.len()
+        );
+
+        // steps count from 1 and end with the log
+        assert_eq!(narrow.diff(0), None);
+        assert_eq!(narrow.diff(moves.len() + 1), None);
+    }
+
+    #[test]
+    fn test_player() {
+        let (map, moves) = parse_input(&fs::read_to_string("test.txt").unwrap());
+        let save_path = env::temp_dir().join("day15_test_player.txt");
+        let mut player = Player {
+            session: Session::new(Warehouse::new(&map, 1)),
+            save_path: Some(save_path.clone()),
+            live: false,
+        };
+
+        // the example's moves, split over lines, with an arrow key, an undo
+        // and a redo on the way
+        let script = "<^^>>\n>vv<\x1b[B\nur>>v<<\nq>>>";
+        let mut output = Vec::new();
+        player.run(script.as_bytes(), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("GPS: 2028  Moves: 15"));
+        assert_eq!(player.session.moves(), moves.iter().collect::<String>());
+        assert_eq!(
+            fs::read_to_string(&save_path).unwrap(),
+            moves.iter().collect::<String>() + "\n"
+        );
+        fs::remove_file(save_path).unwrap();
+
+        // a stray ESC does not swallow the key after it
+        let mut player = Player {
+            session: Session::new(Warehouse::new(&map, 1)),
+            save_path: None,
+            live: false,
+        };
+        player
+            .run("\x1b>\x1b\x1b[Cv\x1b[Z^".as_bytes(), &mut Vec::new())
+            .unwrap();
+        assert_eq!(player.session.moves(), ">>v^");
+    }
+
+    fn solved(warehouse: &Warehouse, moves: &str) -> Warehouse {
+        let mut warehouse = warehouse.clone();
+        for mv in moves.chars() {
+            warehouse.move_robot(mv);
+        }
+        warehouse
+    }
+
+    #[test]
+    fn test_solve_boxes() {
+        let start = "\
+#######
+#.....#
+#.@O..#
+#.....#
+#######";
+        let warehouse = Warehouse::new(start, 1);
+        let target = |map: &str, scale| Goal::Boxes(Warehouse::new(map, scale).boxes);
+
+        let goal = target("#######\n#.....#\n#...O.#\n#.....#\n#######", 1);
+        assert_eq!(solve(&warehouse, &goal, 1000).unwrap().moves, ">");
+
+        let goal = target("#######\n#..O..#\n#.....#\n#.....#\n#######", 1);
+        assert_eq!(solve(&warehouse, &goal, 1000).unwrap().moves, "v>^");
+
+        // a box in the corner stays there, which the search sees straight away
+        let goal = target("#######\n#O....#\n#.....#\n#.....#\n#######", 1);
+        let cornered = Warehouse::new(&start.replace("#.....#\n#.@", "#O....#\n#.@"), 1);
+        assert!(solve(&cornered, &goal, 1000).is_none());
+
+        // pushing into the corner is pruned, but pushing to the target is not
+        let solution = solve(&warehouse, &goal, 1000).unwrap();
+        assert_eq!(
+            solved(&warehouse, &solution.moves).boxes,
+            [Rect {
+                origin: Position { x: 1, y: 1 },
+                width: 1,
+                height: 1,
+            }]
+        );
+        assert_eq!(solution.moves.len(), 7);
+    }
+
+    #[test]
+    fn test_solve_wide() {
+        let start = "\
+#######
+#.....#
+#..O..#
+#..@..#
+#######";
+        let warehouse = Warehouse::new(start, 2);
+        let goal =
+            Goal::Boxes(Warehouse::new("#######\n#...O.#\n#.....#\n#.....#\n#######", 2).boxes);
+
+        // up once, then around its left end and push it two cells right
+        let solution = solve(&warehouse, &goal, 10_000).unwrap();
+        let end = solved(&warehouse, &solution.moves);
+        assert_eq!(end.boxes[0].origin, Position { x: 8, y: 1 });
+        assert_eq!(solution.moves.len(), 5);
+    }
 
-    let result = part1::solve_warehouse_puzzle(&input);
-    println!("Part 1: {}", result);
+    #[test]
+    fn test_solve_gps() {
+        let (map, moves) = parse_input(&fs::read_to_string("test.txt").unwrap());
+        let warehouse = Warehouse::new(&map, 1);
 
-    let result = part2::solve_warehouse_puzzle(&input);
-    println!("Part 2: {}", result);
+        let solution = solve(&warehouse, &Goal::Gps(2028), 1_000_000).unwrap();
+        assert!(solution.moves.len() <= moves.len());
+        assert_eq!(
+            solved(&warehouse, &solution.moves).calculate_gps_coordinates(),
+            2028
+        );
+    }
 }
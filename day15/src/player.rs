@@ -0,0 +1,181 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+#[cfg(unix)]
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+
+#[cfg(unix)]
+use termios::{tcsetattr, Termios, ECHO, ICANON, ISIG, TCSANOW, VMIN, VTIME};
+
+use crate::history::Session;
+
+/// Plays a warehouse from keyboard or piped input.
+///
+/// Moves are `^`, `v`, `<`, `>` or the arrow keys; `u` undoes, `r` redoes,
+/// `w` saves the moves so far and `q` or Ctrl-C quits. Everything else, such
+/// as newlines in piped input, is ignored. With a save file given, the moves
+/// are also saved when the input ends.
+pub struct Player {
+    pub session: Session,
+    /// Where `w` writes the move sequence
+    pub save_path: Option<PathBuf>,
+    /// Redraw after every key, for a terminal in `RawMode`; otherwise only
+    /// draw the end
+    pub live: bool,
+}
+
+/// Hands keys from a terminal over as they are typed, without echoing them,
+/// until dropped. Ctrl-C arrives as a key too, so quitting goes through the
+/// drop, as does unwinding from a panic.
+#[cfg(unix)]
+pub struct RawMode {
+    fd: RawFd,
+    original: Termios,
+}
+
+#[cfg(unix)]
+impl RawMode {
+    pub fn enable(fd: RawFd) -> io::Result<Self> {
+        let original = Termios::from_fd(fd)?;
+        let mut raw = original;
+        raw.c_lflag &= !(ICANON | ECHO | ISIG);
+        raw.c_cc[VMIN] = 1;
+        raw.c_cc[VTIME] = 0;
+        tcsetattr(fd, TCSANOW, &raw)?;
+
+        Ok(RawMode { fd, original })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // nothing left to do if the terminal is gone
+        let _ = tcsetattr(self.fd, TCSANOW, &self.original);
+    }
+}
+
+enum Command {
+    Move(char),
+    Undo,
+    Redo,
+    Save,
+    Quit,
+}
+
+impl Player {
+    pub fn run(&mut self, input: impl BufRead, output: &mut impl Write) -> io::Result<()> {
+        let mut escape = Vec::new();
+
+        for byte in input.bytes() {
+            let byte = byte?;
+
+            // arrow keys arrive as ESC [ A..D
+            let command = match escape.as_slice() {
+                [0x1b] if byte == b'[' => {
+                    escape.push(byte);
+                    continue;
+                }
+                [0x1b, b'['] => {
+                    escape.clear();
+                    match byte {
+                        b'A' => Some(Command::Move('^')),
+                        b'B' => Some(Command::Move('v')),
+                        b'C' => Some(Command::Move('>')),
+                        b'D' => Some(Command::Move('<')),
+                        _ => None,
+                    }
+                }
+                // a lone ESC or none at all: the byte is a key of its own
+                _ => {
+                    escape.clear();
+                    if byte == 0x1b {
+                        escape.push(byte);
+                        continue;
+                    }
+                    match byte {
+                        b'^' | b'v' | b'<' | b'>' => Some(Command::Move(byte as char)),
+                        b'u' => Some(Command::Undo),
+                        b'r' => Some(Command::Redo),
+                        b'w' => Some(Command::Save),
+                        // Ctrl-C in raw mode
+                        b'q' | 0x03 => Some(Command::Quit),
+                        _ => None,
+                    }
+                }
+            };
+            let Some(command) = command else {
+                continue;
+            };
+
+            let status = match command {
+                Command::Move(direction) => {
+                    if self.session.play(direction) {
+                        String::new()
+                    } else {
+                        format!("Blocked: {}", direction)
+                    }
+                }
+                Command::Undo => {
+                    if self.session.undo() {
+                        String::new()
+                    } else {
+                        "Nothing to undo".to_string()
+                    }
+                }
+                Command::Redo => {
+                    if self.session.redo() {
+                        String::new()
+                    } else {
+                        "Nothing to redo".to_string()
+                    }
+                }
+                Command::Save => self.save()?,
+                Command::Quit => break,
+            };
+
+            if self.live {
+                self.render(output, &status)?;
+            }
+        }
+
+        let status = match self.save_path {
+            Some(_) => self.save()?,
+            None => String::new(),
+        };
+        self.render(output, &status)
+    }
+
+    fn save(&self) -> io::Result<String> {
+        match &self.save_path {
+            Some(path) => {
+                fs::write(path, self.session.moves() + "\n")?;
+                Ok(format!(
+                    "Saved {} moves to {}",
+                    self.session.steps(),
+                    path.display()
+                ))
+            }
+            None => Ok("No save file given".to_string()),
+        }
+    }
+
+    fn render(&self, output: &mut impl Write, status: &str) -> io::Result<()> {
+        if self.live {
+            // clear the screen and go to the top left
+            write!(output, "\x1b[2J\x1b[H")?;
+        }
+
+        write!(output, "{}", self.session.warehouse)?;
+        writeln!(
+            output,
+            "GPS: {}  Moves: {}",
+            self.session.warehouse.calculate_gps_coordinates(),
+            self.session.steps()
+        )?;
+        if !status.is_empty() {
+            writeln!(output, "{}", status)?;
+        }
+        output.flush()
+    }
+}
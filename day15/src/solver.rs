@@ -0,0 +1,176 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::warehouse::{Position, Rect, Warehouse};
+
+/// What the boxes should end up like.
+pub enum Goal {
+    /// Every box on one of these rectangles; boxes of equal size are
+    /// interchangeable
+    Boxes(Vec<Rect>),
+    /// Boxes anywhere, as long as their GPS coordinates add up to this
+    Gps(i32),
+}
+
+pub struct Solution {
+    pub moves: String,
+    /// Number of states taken off the queue
+    pub explored: usize,
+}
+
+/// A search state: the robot and the boxes, sorted so that layouts that only
+/// differ in which box is where compare equal.
+type State = (Position, Vec<Rect>);
+
+/// A state, its step count, and its parent node with the move leading here.
+type Node = (State, usize, Option<(usize, char)>);
+
+/// Finds the shortest move sequence reaching `goal` with A*, giving up after
+/// exploring `limit` states.
+///
+/// Each move shifts any box by at most one cell, so the distance from the
+/// farthest box to its nearest target is a lower bound on the moves left.
+/// Layouts with a box frozen off its targets are dropped as deadlocks.
+pub fn solve(warehouse: &Warehouse, goal: &Goal, limit: usize) -> Option<Solution> {
+    let mut scratch = warehouse.clone();
+
+    let mut start_boxes = warehouse.boxes.clone();
+    start_boxes.sort();
+    let start: State = (warehouse.robot, start_boxes);
+
+    if let Goal::Boxes(targets) = goal {
+        let mut sizes: Vec<_> = start
+            .1
+            .iter()
+            .map(|rect| (rect.width, rect.height))
+            .collect();
+        let mut target_sizes: Vec<_> = targets
+            .iter()
+            .map(|rect| (rect.width, rect.height))
+            .collect();
+        sizes.sort();
+        target_sizes.sort();
+        if sizes != target_sizes {
+            return None;
+        }
+    }
+
+    if is_deadlocked(&scratch, &start.1, goal) {
+        return None;
+    }
+
+    let mut nodes: Vec<Node> = vec![(start.clone(), 0, None)];
+    let mut best: HashMap<State, usize> = HashMap::from([(start.clone(), 0)]);
+    let mut queue = BinaryHeap::from([Reverse((estimate(&start.1, goal), 0))]);
+    let mut explored = 0;
+
+    while let Some(Reverse((_, node))) = queue.pop() {
+        let (state, steps, _) = nodes[node].clone();
+        if best[&state] < steps {
+            continue;
+        }
+
+        if reached(&state.1, goal) {
+            let mut moves = Vec::new();
+            let mut current = node;
+            while let Some((parent, direction)) = nodes[current].2 {
+                moves.push(direction);
+                current = parent;
+            }
+            moves.reverse();
+
+            return Some(Solution {
+                moves: moves.into_iter().collect(),
+                explored,
+            });
+        }
+
+        explored += 1;
+        if explored > limit {
+            return None;
+        }
+
+        for direction in ['^', 'v', '<', '>'] {
+            scratch.place(state.0, &state.1);
+            let Some(change) = scratch.move_robot(direction) else {
+                continue;
+            };
+
+            let mut boxes = scratch.boxes.clone();
+            if !change.pushed.is_empty() {
+                boxes.sort();
+                if is_deadlocked(&scratch, &boxes, goal) {
+                    continue;
+                }
+            }
+
+            let next: State = (scratch.robot, boxes);
+            if best.get(&next).is_some_and(|&known| known <= steps + 1) {
+                continue;
+            }
+
+            let priority = steps + 1 + estimate(&next.1, goal);
+            best.insert(next.clone(), steps + 1);
+            nodes.push((next, steps + 1, Some((node, direction))));
+            queue.push(Reverse((priority, nodes.len() - 1)));
+        }
+    }
+
+    None
+}
+
+fn reached(boxes: &[Rect], goal: &Goal) -> bool {
+    match goal {
+        Goal::Boxes(targets) => {
+            let mut targets = targets.clone();
+            targets.sort();
+            boxes == targets
+        }
+        Goal::Gps(gps) => gps_of(boxes) == *gps,
+    }
+}
+
+fn gps_of(boxes: &[Rect]) -> i32 {
+    boxes
+        .iter()
+        .map(|rect| 100 * rect.origin.y + rect.origin.x)
+        .sum()
+}
+
+/// A lower bound on the moves still needed.
+fn estimate(boxes: &[Rect], goal: &Goal) -> usize {
+    match goal {
+        Goal::Boxes(targets) => boxes
+            .iter()
+            .map(|rect| {
+                targets
+                    .iter()
+                    .filter(|target| (target.width, target.height) == (rect.width, rect.height))
+                    .map(|target| {
+                        (rect.origin.x - target.origin.x).abs()
+                            + (rect.origin.y - target.origin.y).abs()
+                    })
+                    .min()
+                    .unwrap_or(0) as usize
+            })
+            .max()
+            .unwrap_or(0),
+        // a move pushes every box by at most one row, changing the score by
+        // at most 100 per box
+        Goal::Gps(gps) => {
+            let per_move = 100 * boxes.len().max(1) as i32;
+            ((gps_of(boxes) - gps).abs() + per_move - 1) as usize / per_move as usize
+        }
+    }
+}
+
+/// Whether a box is stuck somewhere it may not stay. With a GPS goal any
+/// layout might add up, so nothing counts as stuck.
+fn is_deadlocked(warehouse: &Warehouse, boxes: &[Rect], goal: &Goal) -> bool {
+    match goal {
+        Goal::Boxes(targets) => boxes
+            .iter()
+            .any(|rect| !targets.contains(rect) && warehouse.is_frozen(rect)),
+        Goal::Gps(_) => false,
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Position {
+    pub fn offset(self, delta: Position) -> Position {
+        Position {
+            x: self.x + delta.x,
+            y: self.y + delta.y,
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+/// A box covering `width` by `height` cells from its top-left corner `origin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Rect {
+    pub origin: Position,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn cells(&self) -> impl Iterator<Item = Position> + '_ {
+        (0..self.height).flat_map(move |dy| {
+            (0..self.width).map(move |dx| Position {
+                x: self.origin.x + dx,
+                y: self.origin.y + dy,
+            })
+        })
+    }
+}
+
+/// The effect of one successful robot move.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub delta: Position,
+    /// Indices into `Warehouse::boxes` of the boxes pushed along
+    pub pushed: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warehouse {
+    pub walls: HashSet<Position>,
+    pub boxes: Vec<Rect>,
+    /// Index into `boxes` for every cell covered by a box
+    occupied: HashMap<Position, usize>,
+    pub robot: Position,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Warehouse {
+    /// Parses a map, stretching every tile to `scale` cells horizontally:
+    /// walls fill all of them, boxes become one `scale`-wide box and the robot
+    /// stands on the leftmost. Scale 1 is part 1 and scale 2 is part 2.
+    ///
+    /// Maps may also spell out boxes of any width as `[`, `]`, or `[`, `=`…,
+    /// `]`, which are scaled the same way.
+    pub fn new(map: &str, scale: i32) -> Self {
+        assert!(scale > 0, "Scale must be positive");
+
+        let mut walls = HashSet::new();
+        let mut boxes = Vec::new();
+        let mut robot = Position { x: 0, y: 0 };
+        let mut width = 0;
+
+        let lines: Vec<&str> = map.lines().collect();
+
+        for (y, line) in lines.iter().enumerate() {
+            let mut box_start = None;
+
+            for (x, ch) in line.chars().enumerate() {
+                let pos = Position {
+                    x: x as i32 * scale,
+                    y: y as i32,
+                };
+                width = width.max(pos.x + scale);
+
+                match ch {
+                    '#' => {
+                        walls.extend((0..scale).map(|dx| Position {
+                            x: pos.x + dx,
+                            y: pos.y,
+                        }));
+                    }
+                    'O' => boxes.push(Rect {
+                        origin: pos,
+                        width: scale,
+                        height: 1,
+                    }),
+                    '[' => box_start = Some(pos),
+                    ']' => {
+                        let origin = box_start.take().expect("Box closed before it was opened");
+                        boxes.push(Rect {
+                            origin,
+                            width: pos.x + scale - origin.x,
+                            height: 1,
+                        });
+                    }
+                    '@' => {
+                        robot = pos;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut warehouse = Warehouse {
+            walls,
+            boxes,
+            occupied: HashMap::new(),
+            robot,
+            width,
+            height: lines.len() as i32,
+        };
+        warehouse.index_boxes();
+        warehouse
+    }
+
+    /// Puts the robot and the boxes in the given places, keeping the walls.
+    pub fn place(&mut self, robot: Position, boxes: &[Rect]) {
+        self.robot = robot;
+        self.boxes = boxes.to_vec();
+        self.index_boxes();
+    }
+
+    /// Whether a box can never be pushed again: it is held on one side
+    /// horizontally and vertically, as in a corner. A side of a wide box holds
+    /// it if walls touch both it and the opposite side, or all of it, since
+    /// nothing can then push from there.
+    pub fn is_frozen(&self, rect: &Rect) -> bool {
+        let wall = |x: i32, y: i32| self.walls.contains(&Position { x, y });
+        let (left, right) = (rect.origin.x, rect.origin.x + rect.width - 1);
+        let (top, bottom) = (rect.origin.y, rect.origin.y + rect.height - 1);
+
+        let held = |a: Vec<bool>, b: Vec<bool>| {
+            (a.contains(&true) && b.contains(&true)) || !a.contains(&false) || !b.contains(&false)
+        };
+
+        let vertically = held(
+            (left..=right).map(|x| wall(x, top - 1)).collect(),
+            (left..=right).map(|x| wall(x, bottom + 1)).collect(),
+        );
+        let horizontally = held(
+            (top..=bottom).map(|y| wall(left - 1, y)).collect(),
+            (top..=bottom).map(|y| wall(right + 1, y)).collect(),
+        );
+
+        vertically && horizontally
+    }
+
+    fn index_boxes(&mut self) {
+        self.occupied = self
+            .boxes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, rect)| rect.cells().map(move |cell| (cell, i)))
+            .collect();
+    }
+
+    /// Finds every box that moves when the robot steps by `delta`, following
+    /// chains of boxes touching the pushed ones. Returns `None` if any of them,
+    /// or the robot itself, would end up in a wall.
+    fn boxes_to_push(&self, delta: Position) -> Option<Vec<usize>> {
+        let mut pushed = Vec::new();
+        let mut seen = HashSet::new();
+        let mut queue = vec![self.robot.offset(delta)];
+
+        while let Some(pos) = queue.pop() {
+            if self.walls.contains(&pos) {
+                return None;
+            }
+
+            if let Some(&i) = self.occupied.get(&pos) {
+                if seen.insert(i) {
+                    pushed.push(i);
+                    queue.extend(self.boxes[i].cells().map(|cell| cell.offset(delta)));
+                }
+            }
+        }
+
+        Some(pushed)
+    }
+
+    /// Moves the robot one step, pushing any boxes in the way. Returns what
+    /// was displaced, or `None` if the move is blocked or not a direction.
+    pub fn move_robot(&mut self, direction: char) -> Option<Move> {
+        let delta = match direction {
+            '^' => Position { x: 0, y: -1 },
+            'v' => Position { x: 0, y: 1 },
+            '<' => Position { x: -1, y: 0 },
+            '>' => Position { x: 1, y: 0 },
+            _ => return None,
+        };
+
+        let pushed = self.boxes_to_push(delta)?;
+        self.shift(&pushed, delta);
+
+        Some(Move { delta, pushed })
+    }
+
+    /// Reverts a move previously returned by `move_robot`. Moves must be
+    /// undone newest first.
+    pub fn undo_move(&mut self, mv: &Move) {
+        let back = Position {
+            x: -mv.delta.x,
+            y: -mv.delta.y,
+        };
+        self.shift(&mv.pushed, back);
+    }
+
+    /// Moves the robot and the given boxes by `delta`.
+    fn shift(&mut self, pushed: &[usize], delta: Position) {
+        // Clear all pushed boxes before placing them, since they may overlap
+        // their own or each other's old cells
+        for &i in pushed {
+            for cell in self.boxes[i].cells() {
+                self.occupied.remove(&cell);
+            }
+        }
+        for &i in pushed {
+            let rect = &mut self.boxes[i];
+            rect.origin = rect.origin.offset(delta);
+            for cell in rect.cells() {
+                self.occupied.insert(cell, i);
+            }
+        }
+
+        self.robot = self.robot.offset(delta);
+    }
+
+    pub fn calculate_gps_coordinates(&self) -> i32 {
+        self.boxes
+            .iter()
+            .map(|rect| 100 * rect.origin.y + rect.origin.x)
+            .sum()
+    }
+}
+
+impl fmt::Display for Warehouse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pos = Position { x, y };
+                let ch = if self.walls.contains(&pos) {
+                    '#'
+                } else if pos == self.robot {
+                    '@'
+                } else if let Some(&i) = self.occupied.get(&pos) {
+                    let rect = &self.boxes[i];
+                    match (rect.width, x - rect.origin.x) {
+                        (1, _) => 'O',
+                        (_, 0) => '[',
+                        (width, dx) if dx == width - 1 => ']',
+                        _ => '=',
+                    }
+                } else {
+                    '.'
+                };
+                write!(f, "{}", ch)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_input(input: &str) -> (String, Vec<char>) {
+    // Separate map from moves
+    let input = input.replace("\r\n", "\n");
+    let mut parts = input.split("\n\n");
+
+    let map = parts.next().unwrap().to_string();
+
+    // Collect moves, ignoring whitespace
+    let moves: Vec<char> = parts
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|&c| c == '^' || c == 'v' || c == '<' || c == '>')
+        .collect();
+
+    (map, moves)
+}
+
+pub fn solve_warehouse_puzzle(input: &str, scale: i32) -> i32 {
+    let (map, moves) = parse_input(input);
+
+    let mut warehouse = Warehouse::new(&map, scale);
+
+    for mv in moves {
+        warehouse.move_robot(mv);
+    }
+
+    warehouse.calculate_gps_coordinates()
+}
@@ -1,6 +1,13 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::error::Error;
+use std::{env, fmt};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+mod routes;
+
+use routes::{routes_to_json, Route};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 struct Coordinates {
     x: i32,
     y: i32,
@@ -14,157 +21,369 @@ struct State {
 }
 
 #[derive(Debug, Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
 enum Action {
     CW,
     CCW,
     Forward,
+    Backward,
 }
 
-const ACTIONS: [Action; 3] = [Action::CW, Action::CCW, Action::Forward];
-const DIRS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const ACTIONS: [Action; 4] = [Action::CW, Action::CCW, Action::Forward, Action::Backward];
+
+/// Headings in clockwise order, every 45 degrees from east. Without diagonal
+/// moves only the even ones are used.
+const DIRS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+const EAST: usize = 0;
+const SOUTH: usize = 2;
+const WEST: usize = 4;
+const NORTH: usize = 6;
+
+/// What the reindeer may do besides stepping forward and turning by 90
+/// degrees.
+#[derive(Debug, Clone, Copy, Default)]
+struct Movement {
+    /// Turn by 45 degrees for half the turn cost, and step diagonally for a
+    /// step's cost, but not between two walls
+    diagonal: bool,
+    /// Step backward, keeping the heading, at the cost of a step
+    reverse: bool,
+}
+
+/// A position together with the direction faced there.
+type Node = (Coordinates, usize);
+
+/// Scores for the reindeer's actions.
+#[derive(Debug, Clone, Copy)]
+struct Costs {
+    step: i32,
+    turn: i32,
+}
+
+impl Default for Costs {
+    fn default() -> Self {
+        Costs {
+            step: 1,
+            turn: 1000,
+        }
+    }
+}
+
+/// Result of a Dijkstra search: the best score at the end, and for every node
+/// on some best route the nodes it can be reached from at its best score.
+struct ShortestPaths {
+    starts: Vec<Node>,
+    score: i32,
+    ends: Vec<Node>,
+    predecessors: HashMap<Node, Vec<Node>>,
+}
+
+impl ShortestPaths {
+    /// All tiles on at least one best route, found by walking the
+    /// predecessor DAG back from the end.
+    fn tiles(&self) -> HashSet<Coordinates> {
+        let mut seen: HashSet<Node> = self.ends.iter().copied().collect();
+        let mut stack = self.ends.clone();
+
+        while let Some(node) = stack.pop() {
+            for &previous in self.predecessors.get(&node).into_iter().flatten() {
+                if seen.insert(previous) {
+                    stack.push(previous);
+                }
+            }
+        }
+
+        seen.into_iter().map(|(pos, _)| pos).collect()
+    }
+
+    /// One best route, following the first predecessor of every node. Those
+    /// form a tree, as each was the node that last lowered the score.
+    fn route(&self) -> Route {
+        let mut nodes = vec![self.ends[0]];
+        while !self.starts.contains(nodes.last().unwrap()) {
+            nodes.push(self.predecessors[nodes.last().unwrap()][0]);
+        }
+        nodes.reverse();
+
+        Route {
+            score: self.score,
+            nodes,
+        }
+    }
+}
+
+/// Nodes, tiles and edges a search may not use.
+#[derive(Default)]
+struct Banned {
+    nodes: HashSet<Node>,
+    tiles: HashSet<Coordinates>,
+    edges: HashSet<(Node, Node)>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum MazeError {
+    MissingStart,
+    MissingEnd,
+}
+
+impl fmt::Display for MazeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MazeError::MissingStart => write!(f, "Maze has no start tile 'S'"),
+            MazeError::MissingEnd => write!(f, "Maze has no end tile 'E'"),
+        }
+    }
+}
+
+impl Error for MazeError {}
 
 struct Maze {
     walls: HashSet<Coordinates>,
-    start: Coordinates,
-    end: Coordinates,
+    starts: Vec<Coordinates>,
+    ends: Vec<Coordinates>,
+    /// Heading at every start
+    facing: usize,
+    movement: Movement,
 }
 
 impl Maze {
-    /// Parses the input map into a `Grid`.
-    fn from_input(input: &str) -> Self {
+    /// Parses the input map into a `Maze`, with a start at every `S` and an
+    /// end at every `E`. The reindeer starts facing east with the puzzle's
+    /// movement.
+    fn from_input(input: &str) -> Result<Self, MazeError> {
         let mut walls = HashSet::new();
-        let mut start = Coordinates { x: 0, y: 0 };
-        let mut end = Coordinates { x: 0, y: 0 };
+        let mut starts = Vec::new();
+        let mut ends = Vec::new();
 
         for (y, line) in input.lines().enumerate() {
             for (x, c) in line.chars().enumerate() {
+                let pos = Coordinates {
+                    x: x as i32,
+                    y: y as i32,
+                };
                 match c {
-                    'S' => {
-                        start = Coordinates {
-                            x: x as i32,
-                            y: y as i32,
-                        };
-                    }
-                    'E' => {
-                        end = Coordinates {
-                            x: x as i32,
-                            y: y as i32,
-                        };
-                    }
+                    'S' => starts.push(pos),
+                    'E' => ends.push(pos),
                     '#' => {
-                        walls.insert(Coordinates {
-                            x: x as i32,
-                            y: y as i32,
-                        });
+                        walls.insert(pos);
                     }
                     _ => (),
                 }
             }
         }
 
-        Maze { walls, start, end }
+        if starts.is_empty() {
+            return Err(MazeError::MissingStart);
+        }
+        if ends.is_empty() {
+            return Err(MazeError::MissingEnd);
+        }
+
+        Ok(Maze {
+            walls,
+            starts,
+            ends,
+            facing: EAST,
+            movement: Movement::default(),
+        })
     }
 
-    /// Finds the shortest path from start to end.
-    fn find_shortest_path(&self) -> (i32, usize) {
-        let initial_state = State {
-            pos: self.start,
-            dir: 0,
-            score: 0,
-        };
+    fn with_facing(self, facing: usize) -> Self {
+        Maze { facing, ..self }
+    }
 
-        // create queue of (state, path)
-        let mut queue = VecDeque::from([(initial_state, vec![initial_state.pos])]);
+    fn with_movement(self, movement: Movement) -> Self {
+        Maze { movement, ..self }
+    }
+
+    /// Runs Dijkstra over `(pos, dir)` nodes from all starts at once until
+    /// every best route to the nearest end is known. Returns `None` if no end
+    /// can be reached.
+    fn shortest_paths(&self, costs: Costs) -> Option<ShortestPaths> {
+        let starts: Vec<Node> = self.starts.iter().map(|&pos| (pos, self.facing)).collect();
+        self.search(&starts, costs, &Banned::default())
+    }
 
-        // keep track of visited states
-        let mut visited = HashMap::new();
+    /// Like `shortest_paths`, but from any nodes and without using anything
+    /// in `banned`.
+    ///
+    /// # Panics
+    ///
+    /// Panics on negative costs, which Dijkstra cannot handle, and on an odd
+    /// turn cost with diagonal movement, which could not be halved exactly.
+    fn search(&self, starts: &[Node], costs: Costs, banned: &Banned) -> Option<ShortestPaths> {
+        assert!(
+            costs.step >= 0 && costs.turn >= 0,
+            "Costs must not be negative: {:?}",
+            costs
+        );
+        assert!(
+            !self.movement.diagonal || costs.turn % 2 == 0,
+            "Turn cost must be even with diagonal movement: {:?}",
+            costs
+        );
 
-        let mut best_path_positions = HashSet::new();
-        let mut best_score = i32::MAX;
+        let mut scores: HashMap<Node, i32> = starts.iter().map(|&node| (node, 0)).collect();
+        let mut predecessors: HashMap<Node, Vec<Node>> = HashMap::new();
+        let mut queue: BinaryHeap<_> = starts.iter().map(|&node| Reverse((0, node))).collect();
 
-        while let Some((state, path)) = queue.pop_front() {
-            if state.pos == self.end {
-                if state.score < best_score {
-                    best_score = state.score;
-                    best_path_positions = path.into_iter().collect();
-                } else if state.score == best_score {
-                    best_path_positions.extend(path.iter());
-                }
+        let mut best_score = None;
+        let mut ends = Vec::new();
+
+        while let Some(Reverse((score, node))) = queue.pop() {
+            if scores[&node] < score {
                 continue;
             }
-
-            if let Some(&prev_score) = visited.get(&(state.pos, state.dir)) {
-                if prev_score < state.score {
-                    continue;
-                }
+            if best_score.is_some_and(|best| score > best) {
+                break;
             }
 
-            visited.insert((state.pos, state.dir), state.score);
-
-            if state.score >= best_score {
+            if self.ends.contains(&node.0) {
+                best_score = Some(score);
+                ends.push(node);
                 continue;
             }
 
-            queue.extend(
-                ACTIONS
-                    .iter()
-                    .filter_map(|&action| self.apply_action(state, action))
-                    .map(|next_state| (next_state, [path.clone(), vec![next_state.pos]].concat())),
-            );
+            let state = State {
+                pos: node.0,
+                dir: node.1,
+                score,
+            };
+            for next in ACTIONS
+                .iter()
+                .filter_map(|&action| self.apply_action(state, action, costs))
+            {
+                let next_node = (next.pos, next.dir);
+                if banned.nodes.contains(&next_node)
+                    || banned.tiles.contains(&next.pos)
+                    || banned.edges.contains(&(node, next_node))
+                {
+                    continue;
+                }
+                let known = scores.get(&next_node).copied().unwrap_or(i32::MAX);
+
+                if next.score < known {
+                    scores.insert(next_node, next.score);
+                    predecessors.insert(next_node, vec![node]);
+                    queue.push(Reverse((next.score, next_node)));
+                } else if next.score == known {
+                    predecessors.entry(next_node).or_default().push(node);
+                }
+            }
         }
 
-        (best_score, best_path_positions.len())
+        Some(ShortestPaths {
+            starts: starts.to_vec(),
+            score: best_score?,
+            ends,
+            predecessors,
+        })
+    }
+
+    /// Finds the best score from start to end and the number of tiles on any
+    /// best route.
+    fn find_shortest_path(&self, costs: Costs) -> Option<(i32, usize)> {
+        let paths = self.shortest_paths(costs)?;
+        Some((paths.score, paths.tiles().len()))
     }
 
-    fn _visualize_paths(&self, path: HashSet<Coordinates>) {
+    /// Draws the maze with the tiles of `path` marked `O`.
+    pub fn visualize_paths(&self, path: &HashSet<Coordinates>) -> String {
         let max_x = self.walls.iter().max_by_key(|c| c.x).unwrap().x;
         let max_y = self.walls.iter().max_by_key(|c| c.y).unwrap().y;
 
+        let mut picture = String::new();
         for y in 0..=max_y {
             for x in 0..=max_x {
                 let c = Coordinates { x, y };
 
-                if self.walls.contains(&c) {
-                    print!("#");
+                picture.push(if self.walls.contains(&c) {
+                    '#'
                 } else if path.contains(&c) {
-                    print!("O");
-                } else if c == self.start {
-                    print!("S");
-                } else if c == self.end {
-                    print!("E");
+                    'O'
+                } else if self.starts.contains(&c) {
+                    'S'
+                } else if self.ends.contains(&c) {
+                    'E'
                 } else {
-                    print!(".");
-                }
+                    '.'
+                });
             }
-            println!("");
+            picture.push('\n');
+        }
+        picture
+    }
+
+    /// Score of a single turn action.
+    fn turn_cost(&self, costs: Costs) -> i32 {
+        if self.movement.diagonal {
+            costs.turn / 2
+        } else {
+            costs.turn
         }
-        println!();
     }
 
     /// Applies an action to a given state, returning the resulting state if valid.
-    fn apply_action(&self, state: State, action: Action) -> Option<State> {
+    fn apply_action(&self, state: State, action: Action, costs: Costs) -> Option<State> {
         let mut new_pos = state.pos;
         let mut new_dir = state.dir;
         let mut new_score = state.score;
 
+        // a turn is 90 degrees, or 45 with diagonal moves
+        let turn = if self.movement.diagonal { 1 } else { 2 };
+
         match action {
             Action::CW => {
-                new_dir = (state.dir + 1) % 4;
-                new_score += 1000;
+                new_dir = (state.dir + turn) % 8;
+                new_score += self.turn_cost(costs);
             }
             Action::CCW => {
-                new_dir = (state.dir + 3) % 4;
-                new_score += 1000;
+                new_dir = (state.dir + 8 - turn) % 8;
+                new_score += self.turn_cost(costs);
             }
-            Action::Forward => {
+            Action::Forward | Action::Backward => {
+                let sign = match action {
+                    Action::Backward if !self.movement.reverse => return None,
+                    Action::Backward => -1,
+                    _ => 1,
+                };
+                let (dx, dy) = DIRS[state.dir];
+                let (dx, dy) = (sign * dx, sign * dy);
+
                 new_pos = Coordinates {
-                    x: state.pos.x + DIRS[state.dir].0,
-                    y: state.pos.y + DIRS[state.dir].1,
+                    x: state.pos.x + dx,
+                    y: state.pos.y + dy,
                 };
                 if self.walls.contains(&new_pos) {
                     return None;
                 }
-                new_score += 1;
+
+                // no squeezing diagonally between two walls
+                let beside = [
+                    Coordinates {
+                        x: state.pos.x + dx,
+                        y: state.pos.y,
+                    },
+                    Coordinates {
+                        x: state.pos.x,
+                        y: state.pos.y + dy,
+                    },
+                ];
+                if dx != 0 && dy != 0 && beside.iter().all(|c| self.walls.contains(c)) {
+                    return None;
+                }
+
+                new_score += costs.step;
             }
         }
 
@@ -176,12 +395,219 @@ impl Maze {
     }
 }
 
-fn main() {
-    let input = std::fs::read_to_string("input.txt").unwrap();
-    let grid = Maze::from_input(&input);
+/// Usage: day16 [k] [json file] [--facing N|E|S|W] [--diagonal] [--reverse]
+///
+/// Lists the k best routes, optionally exporting them, with the given
+/// starting heading and movement.
+fn main() -> Result<(), Box<dyn Error>> {
+    let input = std::fs::read_to_string("input.txt")?;
+    let grid = Maze::from_input(&input)?;
 
-    let (best_score, num_best_path_positions) = grid.find_shortest_path();
+    let (best_score, num_best_path_positions) = grid
+        .find_shortest_path(Costs::default())
+        .expect("No path to the end");
 
     println!("Part 1: {}", best_score);
     println!("Part 2: {}", num_best_path_positions);
+
+    let mut positional = Vec::new();
+    let mut facing = EAST;
+    let mut movement = Movement::default();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--facing" => {
+                facing = match args.next().as_deref() {
+                    Some("N") => NORTH,
+                    Some("E") => EAST,
+                    Some("S") => SOUTH,
+                    Some("W") => WEST,
+                    _ => return Err("--facing needs one of N, E, S or W".into()),
+                }
+            }
+            "--diagonal" => movement.diagonal = true,
+            "--reverse" => movement.reverse = true,
+            _ => positional.push(arg),
+        }
+    }
+
+    if let Some(k) = positional.first() {
+        let k = k.parse()?;
+        let grid = grid.with_facing(facing).with_movement(movement);
+        let routes = grid.k_best_routes(Costs::default(), k);
+
+        for (i, route) in routes.iter().enumerate() {
+            println!(
+                "Route {}: score {}, {} turns",
+                i + 1,
+                route.score,
+                route.turns().len()
+            );
+            println!("{}", grid.visualize_paths(&route.tiles()));
+        }
+
+        if let Some(path) = positional.get(1) {
+            std::fs::write(path, routes_to_json(&routes))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_examples() {
+        let maze = Maze::from_input(&std::fs::read_to_string("test1.txt").unwrap()).unwrap();
+        assert_eq!(maze.find_shortest_path(Costs::default()), Some((7036, 45)));
+
+        let maze = Maze::from_input(&std::fs::read_to_string("test2.txt").unwrap()).unwrap();
+        assert_eq!(maze.find_shortest_path(Costs::default()), Some((11048, 64)));
+    }
+
+    #[test]
+    fn test_costs() {
+        let maze = Maze::from_input("#####\n#..E#\n#.#.#\n#S..#\n#####").unwrap();
+
+        // going east first needs one turn, going north first two
+        assert_eq!(maze.find_shortest_path(Costs::default()), Some((1004, 5)));
+
+        // with free turns both ways round are best
+        let free_turns = Costs { step: 1, turn: 0 };
+        assert_eq!(maze.find_shortest_path(free_turns), Some((4, 8)));
+
+        let walled = Maze::from_input("#####\n#S#E#\n#####").unwrap();
+        assert_eq!(walled.find_shortest_path(Costs::default()), None);
+    }
+
+    #[test]
+    fn test_k_best_routes() {
+        let maze = Maze::from_input(&std::fs::read_to_string("test1.txt").unwrap()).unwrap();
+        let routes = maze.k_best_routes(Costs::default(), 5);
+
+        let scores: Vec<i32> = routes.iter().map(|route| route.score).collect();
+        assert_eq!(&scores[..3], [7036; 3]);
+        assert!(scores.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(scores[3] > 7036);
+
+        // the best routes cover exactly the best-path tiles
+        let tiles: HashSet<Coordinates> = routes[..3].iter().flat_map(Route::tiles).collect();
+        assert_eq!(tiles.len(), 45);
+
+        for route in &routes {
+            let turns = route.nodes.windows(2).filter(|pair| pair[0].1 != pair[1].1);
+            let steps = route.nodes.len() - 1 - turns.count();
+            assert_eq!(
+                route.score,
+                steps as i32 + 1000 * route.turns().len() as i32
+            );
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let maze = Maze::from_input("#####\n#..E#\n#.#.#\n#S..#\n#####").unwrap();
+        let routes = maze.k_best_routes(Costs::default(), 3);
+        assert_eq!(routes.len(), 2);
+
+        assert_eq!(
+            routes_to_json(&routes),
+            r#"[
+  {"score": 1004, "tiles": [[1, 3], [2, 3], [3, 3], [3, 2], [3, 1]], "turns": [{"x": 3, "y": 3, "turn": "left"}]},
+  {"score": 2004, "tiles": [[1, 3], [1, 2], [1, 1], [2, 1], [3, 1]], "turns": [{"x": 1, "y": 3, "turn": "left"}, {"x": 1, "y": 1, "turn": "right"}]}
+]
+"#
+        );
+
+        assert_eq!(
+            maze.visualize_paths(&routes[1].tiles()),
+            "#####\n#OOO#\n#O#.#\n#O..#\n#####\n"
+        );
+    }
+
+    #[test]
+    fn test_markers() {
+        assert_eq!(
+            Maze::from_input("#####\n#S..#\n#####").err(),
+            Some(MazeError::MissingEnd)
+        );
+        assert_eq!(
+            Maze::from_input("#####\n#..E#\n#####").err(),
+            Some(MazeError::MissingStart)
+        );
+
+        // two corridors, one leading east and one west
+        let maze =
+            Maze::from_input("#########\n#S.....E#\n#########\n#.E...S.#\n#########").unwrap();
+        assert_eq!(maze.find_shortest_path(Costs::default()), Some((6, 7)));
+
+        let scores: Vec<i32> = maze
+            .k_best_routes(Costs::default(), 2)
+            .iter()
+            .map(|route| route.score)
+            .collect();
+        assert_eq!(scores, [6, 2004]);
+
+        let maze = maze.with_facing(WEST);
+        assert_eq!(maze.find_shortest_path(Costs::default()), Some((4, 5)));
+    }
+
+    #[test]
+    fn test_movement() {
+        let corridor = Maze::from_input("#####\n#E.S#\n#####").unwrap();
+        assert_eq!(
+            corridor.find_shortest_path(Costs::default()),
+            Some((2002, 3))
+        );
+
+        let reverse = Movement {
+            reverse: true,
+            ..Movement::default()
+        };
+        let corridor = corridor.with_movement(reverse);
+        assert_eq!(corridor.find_shortest_path(Costs::default()), Some((2, 3)));
+
+        let diagonal = Movement {
+            diagonal: true,
+            ..Movement::default()
+        };
+        let room = Maze::from_input("#####\n#..E#\n#...#\n#S..#\n#####").unwrap();
+        assert_eq!(room.find_shortest_path(Costs::default()).unwrap().0, 1004);
+
+        let room = room.with_movement(diagonal);
+        assert_eq!(room.find_shortest_path(Costs::default()), Some((502, 3)));
+        let routes = room.k_best_routes(Costs::default(), 1);
+        assert_eq!(
+            routes[0].turns(),
+            [(Coordinates { x: 1, y: 3 }, routes::Turn::Left)]
+        );
+
+        // the gap between two walls touching at a corner is too narrow
+        let squeeze = Maze::from_input("####\n##E#\n#S##\n####")
+            .unwrap()
+            .with_movement(diagonal);
+        assert_eq!(squeeze.find_shortest_path(Costs::default()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Costs must not be negative")]
+    fn test_negative_costs() {
+        let maze = Maze::from_input(&std::fs::read_to_string("test1.txt").unwrap()).unwrap();
+        maze.find_shortest_path(Costs { step: 1, turn: -1 });
+    }
+
+    #[test]
+    #[should_panic(expected = "Turn cost must be even")]
+    fn test_odd_diagonal_turn() {
+        let maze = Maze::from_input(&std::fs::read_to_string("test1.txt").unwrap())
+            .unwrap()
+            .with_movement(Movement {
+                diagonal: true,
+                ..Movement::default()
+            });
+        maze.find_shortest_path(Costs { step: 1, turn: 999 });
+    }
 }
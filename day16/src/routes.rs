@@ -0,0 +1,197 @@
+use std::collections::HashSet;
+
+use crate::{Banned, Coordinates, Costs, Maze, Node};
+
+/// A route through the maze as the nodes visited, start and end included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub score: i32,
+    pub nodes: Vec<Node>,
+}
+
+/// A turn on the spot, looking from above with y pointing down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Turn {
+    Left,
+    Right,
+}
+
+impl Route {
+    pub fn tiles(&self) -> HashSet<Coordinates> {
+        self.nodes.iter().map(|&(pos, _)| pos).collect()
+    }
+
+    /// The node each step forward is taken from, plus the final node: the
+    /// route without the detail of how it turned on the spot.
+    fn steps(&self) -> Vec<Node> {
+        let mut steps: Vec<Node> = self
+            .nodes
+            .windows(2)
+            .filter(|pair| pair[0].0 != pair[1].0)
+            .map(|pair| pair[0])
+            .collect();
+        steps.push(*self.nodes.last().unwrap());
+        steps
+    }
+
+    /// Every turn taken, in order, with the tile it is taken on.
+    pub fn turns(&self) -> Vec<(Coordinates, Turn)> {
+        self.nodes
+            .windows(2)
+            .filter(|pair| pair[0].1 != pair[1].1)
+            .map(|pair| {
+                // clockwise is a right turn
+                let turn = if (pair[1].1 + 8 - pair[0].1) % 8 < 4 {
+                    Turn::Right
+                } else {
+                    Turn::Left
+                };
+                (pair[0].0, turn)
+            })
+            .collect()
+    }
+}
+
+impl Maze {
+    /// Score of going from one node to the next on a route.
+    fn edge_score(&self, from: Node, to: Node, costs: Costs) -> i32 {
+        if from.1 == to.1 {
+            costs.step
+        } else {
+            self.turn_cost(costs)
+        }
+    }
+
+    /// The `k` cheapest routes from any start to an end, cheapest first.
+    pub fn k_best_routes(&self, costs: Costs, k: usize) -> Vec<Route> {
+        let mut routes: Vec<Route> = self
+            .starts
+            .iter()
+            .flat_map(|&start| self.k_best_routes_from((start, self.facing), costs, k))
+            .collect();
+
+        routes.sort_by_key(|route| route.score);
+        routes.truncate(k);
+        routes
+    }
+
+    /// The `k` cheapest routes from `start`, by Yen's algorithm over the
+    /// `(pos, dir)` graph.
+    ///
+    /// Every further route leaves one of the routes found so far at some spur
+    /// node, and from there takes the cheapest way that neither goes back
+    /// through its own root nor repeats the next edge of any route sharing
+    /// that root. Routes only come back to a tile by turning on it, and routes
+    /// that only differ in how they turn on the spot, such as three right
+    /// turns instead of one left, count as one.
+    fn k_best_routes_from(&self, start: Node, costs: Costs, k: usize) -> Vec<Route> {
+        let Some(best) = self.search(&[start], costs, &Banned::default()) else {
+            return Vec::new();
+        };
+
+        let mut found = vec![best.route()];
+        let mut candidates: Vec<Route> = Vec::new();
+
+        while found.len() < k {
+            let previous = found.last().unwrap().clone();
+
+            let mut root_score = 0;
+            for i in 0..previous.nodes.len() - 1 {
+                let spur = previous.nodes[i];
+                let root = &previous.nodes[..=i];
+                if i > 0 {
+                    root_score += self.edge_score(previous.nodes[i - 1], spur, costs);
+                }
+
+                let banned = Banned {
+                    nodes: root[..i].iter().copied().collect(),
+                    tiles: root[..i]
+                        .iter()
+                        .map(|&(pos, _)| pos)
+                        .filter(|&pos| pos != spur.0)
+                        .collect(),
+                    edges: found
+                        .iter()
+                        .filter(|route| route.nodes.len() > i + 1 && route.nodes[..=i] == *root)
+                        .map(|route| (route.nodes[i], route.nodes[i + 1]))
+                        .collect(),
+                };
+
+                let Some(spur_paths) = self.search(&[spur], costs, &banned) else {
+                    continue;
+                };
+                let spur_route = spur_paths.route();
+
+                let mut nodes = root[..i].to_vec();
+                nodes.extend(spur_route.nodes);
+                let candidate = Route {
+                    score: root_score + spur_route.score,
+                    nodes,
+                };
+
+                if !candidates.contains(&candidate) && !found.contains(&candidate) {
+                    candidates.push(candidate);
+                }
+            }
+
+            // the cheapest candidate, oldest first on ties, that is not just a
+            // costlier way of turning along a route already found
+            loop {
+                let Some(next) = (0..candidates.len()).min_by_key(|&i| candidates[i].score) else {
+                    return found;
+                };
+                let next = candidates.remove(next);
+                if found.iter().all(|route| route.steps() != next.steps()) {
+                    found.push(next);
+                    break;
+                }
+            }
+        }
+
+        found
+    }
+}
+
+/// Writes routes as a JSON array of objects with their score, tiles in order
+/// and turns.
+pub fn routes_to_json(routes: &[Route]) -> String {
+    let entries: Vec<String> = routes
+        .iter()
+        .map(|route| {
+            let tiles: Vec<String> = route
+                .nodes
+                .iter()
+                .map(|&(pos, _)| pos)
+                .fold(Vec::new(), |mut tiles: Vec<Coordinates>, pos| {
+                    if tiles.last() != Some(&pos) {
+                        tiles.push(pos);
+                    }
+                    tiles
+                })
+                .iter()
+                .map(|pos| format!("[{}, {}]", pos.x, pos.y))
+                .collect();
+
+            let turns: Vec<String> = route
+                .turns()
+                .iter()
+                .map(|(pos, turn)| {
+                    let turn = match turn {
+                        Turn::Left => "left",
+                        Turn::Right => "right",
+                    };
+                    format!(r#"{{"x": {}, "y": {}, "turn": "{}"}}"#, pos.x, pos.y, turn)
+                })
+                .collect();
+
+            format!(
+                "  {{\"score\": {}, \"tiles\": [{}], \"turns\": [{}]}}",
+                route.score,
+                tiles.join(", "),
+                turns.join(", ")
+            )
+        })
+        .collect();
+
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
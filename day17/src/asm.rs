@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::disasm::{Opcode, OPCODES};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AssemblyError {
+    /// Line number, counting from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssemblyError {}
+
+/// Assembles a program written as mnemonics into the puzzle's input format.
+///
+/// ```text
+/// ; anything after a semicolon is a comment
+/// .a 729          ; initial registers, 0 unless given
+/// loop:           ; a label names the address of the next instruction
+///     adv 1
+///     out A       ; combo operands are 0 to 3 or a register
+///     jnz loop    ; jump targets are labels or addresses
+/// ```
+///
+/// Operands are checked: combo operands may not be 7, literals must fit in 3
+/// bits, and so must the address of any label a `jnz` jumps to.
+pub fn assemble(source: &str) -> Result<String, AssemblyError> {
+    let mut registers = [0u64; 3];
+    let mut labels = HashMap::new();
+    // instructions with their line, opcode and operand text
+    let mut instructions = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let error = |message: String| AssemblyError {
+            line: number,
+            message,
+        };
+
+        let mut code = line.split(';').next().unwrap().trim();
+
+        if let Some((label, rest)) = code.split_once(':') {
+            let label = label.trim();
+            if label.is_empty() || !label.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(error(format!("Invalid label {:?}", label)));
+            }
+            if labels
+                .insert(label.to_string(), instructions.len() * 2)
+                .is_some()
+            {
+                return Err(error(format!("Label {} defined twice", label)));
+            }
+            code = rest.trim();
+        }
+
+        let mut words = code.split_whitespace();
+        let Some(word) = words.next() else {
+            continue;
+        };
+        let operand = words.next();
+        if words.next().is_some() {
+            return Err(error(format!("Too many operands in {:?}", code)));
+        }
+
+        if let Some(register) = word.strip_prefix('.') {
+            let index = match register {
+                "a" => 0,
+                "b" => 1,
+                "c" => 2,
+                _ => return Err(error(format!("Unknown directive .{}", register))),
+            };
+            registers[index] = operand
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(|| error(format!("Directive .{} needs a number", register)))?;
+            continue;
+        }
+
+        let opcode = OPCODES
+            .iter()
+            .position(|opcode| opcode.mnemonic() == word)
+            .ok_or_else(|| error(format!("Unknown instruction {}", word)))?;
+        instructions.push((number, OPCODES[opcode], operand));
+    }
+
+    let mut program = Vec::with_capacity(instructions.len() * 2);
+    for (number, opcode, operand) in instructions {
+        let error = |message: String| AssemblyError {
+            line: number,
+            message,
+        };
+        let literal = |text: &str| match text.parse::<u64>() {
+            Ok(value) if value < 8 => Ok(value),
+            Ok(value) => Err(error(format!("Operand {} does not fit in 3 bits", value))),
+            Err(_) => Err(error(format!("Invalid operand {}", text))),
+        };
+
+        let value = match (opcode, operand) {
+            (Opcode::Bxc, None) => 0,
+            (Opcode::Bxc, Some(text)) => literal(text)?,
+            (_, None) => return Err(error(format!("{} needs an operand", opcode.mnemonic()))),
+            (Opcode::Jnz, Some(text)) => match labels.get(text) {
+                Some(&address) if address < 8 => address as u64,
+                Some(&address) => {
+                    return Err(error(format!(
+                        "Label {} is at address {}, out of reach of jnz",
+                        text, address
+                    )))
+                }
+                None if text.starts_with(|c: char| c.is_ascii_digit()) => literal(text)?,
+                None => return Err(error(format!("Unknown label {}", text))),
+            },
+            (_, Some(text)) if opcode.takes_combo() => match text {
+                "A" => 4,
+                "B" => 5,
+                "C" => 6,
+                _ => match literal(text)? {
+                    7 => return Err(error("Combo operand 7 is reserved".to_string())),
+                    value => value,
+                },
+            },
+            (_, Some(text)) => literal(text)?,
+        };
+
+        program.push(opcode as u64);
+        program.push(value);
+    }
+
+    Ok(format!(
+        "Register A: {}\nRegister B: {}\nRegister C: {}\n\nProgram: {}\n",
+        registers[0],
+        registers[1],
+        registers[2],
+        program
+            .iter()
+            .map(u64::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    ))
+}
@@ -0,0 +1,395 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::disasm::{Instruction, Operand};
+use crate::Computer;
+
+/// Steps `continue` takes before giving up on reaching a breakpoint.
+const STEP_LIMIT: usize = 1_000_000;
+
+const REGISTERS: [&str; 3] = ["A", "B", "C"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Comparison {
+    const SYMBOLS: [(&'static str, Comparison); 6] = [
+        // two-character symbols first, so `<=` is not read as `<`
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessEqual),
+        (">=", Comparison::GreaterEqual),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    fn holds(self, left: u64, right: u64) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterEqual => left >= right,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        Self::SYMBOLS
+            .iter()
+            .find(|(_, comparison)| *comparison == self)
+            .unwrap()
+            .0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// The instruction pointer reaching an address
+    Address(usize),
+    /// A register comparing to a value, such as `A == 0`
+    Condition(usize, Comparison, u64),
+    /// A register changing value
+    Watch(usize),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Breakpoint::Address(address) => write!(f, "at {:02}", address),
+            Breakpoint::Condition(register, comparison, value) => write!(
+                f,
+                "when {} {} {}",
+                REGISTERS[register],
+                comparison.symbol(),
+                value
+            ),
+            Breakpoint::Watch(register) => write!(f, "when {} changes", REGISTERS[register]),
+        }
+    }
+}
+
+impl Breakpoint {
+    /// Parses `12`, `A==0`, `B > 7` and the like.
+    fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if let Ok(address) = text.parse() {
+            return Some(Breakpoint::Address(address));
+        }
+
+        let (register, comparison, value) =
+            Comparison::SYMBOLS
+                .iter()
+                .find_map(|&(symbol, comparison)| {
+                    let (register, value) = text.split_once(symbol)?;
+                    Some((register.trim(), comparison, value.trim()))
+                })?;
+        Some(Breakpoint::Condition(
+            register_index(register)?,
+            comparison,
+            value.parse().ok()?,
+        ))
+    }
+
+    /// Whether the breakpoint fires on the computer as it is after a step
+    /// from `before`.
+    fn hit(&self, before: &Snapshot, computer: &Computer) -> bool {
+        match *self {
+            Breakpoint::Address(address) => computer.pos == address,
+            Breakpoint::Condition(register, comparison, value) => {
+                comparison.holds(computer.registers[register], value)
+            }
+            Breakpoint::Watch(register) => {
+                computer.registers[register] != before.registers[register]
+            }
+        }
+    }
+}
+
+fn register_index(name: &str) -> Option<usize> {
+    REGISTERS
+        .iter()
+        .position(|register| register.eq_ignore_ascii_case(name))
+}
+
+/// The state before a step, enough to take it back.
+#[derive(Debug, Clone)]
+struct Snapshot {
+    registers: Vec<u64>,
+    pos: usize,
+    output_len: usize,
+}
+
+/// Steps through a program under commands read one per line:
+///
+/// - `step [n]` or `s [n]` runs one or `n` instructions
+/// - `back [n]` or `b [n]` takes steps back
+/// - `continue` or `c` runs until a breakpoint fires or the program halts
+/// - `break ADDR` or `break A==0` (also `!=`, `<`, `<=`, `>`, `>=`) sets a
+///   breakpoint, `watch A` breaks when the register changes
+/// - `delete N` removes breakpoint `N`, `breakpoints` lists them
+/// - `regs` or `r` prints the registers in decimal, octal and binary
+/// - `trace [n]` lists the last ten or `n` instructions executed
+/// - `output` or `o` prints the output so far, `quit` or `q` stops
+pub struct Debugger {
+    pub computer: Computer,
+    pub output: Vec<u64>,
+    pub breakpoints: Vec<Breakpoint>,
+    /// The state before each step taken, oldest first
+    trace: Vec<Snapshot>,
+}
+
+impl Debugger {
+    pub fn new(computer: Computer) -> Self {
+        Self {
+            computer,
+            output: Vec::new(),
+            breakpoints: Vec::new(),
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn run(&mut self, input: impl BufRead, output: &mut impl Write) -> io::Result<()> {
+        writeln!(output, "{}", self.location())?;
+
+        for line in input.lines() {
+            let line = line?;
+            let mut words = line.split_whitespace();
+            let Some(command) = words.next() else {
+                continue;
+            };
+            let argument = words.collect::<Vec<_>>().join(" ");
+            let count = || match argument.as_str() {
+                "" => Some(1),
+                text => text.parse().ok(),
+            };
+
+            let reply = match command {
+                "step" | "s" => match count() {
+                    Some(count) => {
+                        let taken = (0..count).take_while(|_| self.step()).count();
+                        self.after_steps(taken, count)
+                    }
+                    None => format!("Invalid count: {}", argument),
+                },
+                "back" | "b" => match count() {
+                    Some(count) => {
+                        let taken = (0..count).take_while(|_| self.back()).count();
+                        if taken < count {
+                            format!("At the start\n{}", self.location())
+                        } else {
+                            self.location()
+                        }
+                    }
+                    None => format!("Invalid count: {}", argument),
+                },
+                "continue" | "c" => self.resume(),
+                "break" => match Breakpoint::parse(&argument) {
+                    Some(breakpoint) => self.add_breakpoint(breakpoint),
+                    None => format!("Invalid breakpoint: {}", argument),
+                },
+                "watch" => match register_index(&argument) {
+                    Some(register) => self.add_breakpoint(Breakpoint::Watch(register)),
+                    None => format!("Invalid register: {}", argument),
+                },
+                "delete" => match argument.parse::<usize>() {
+                    Ok(number) if (1..=self.breakpoints.len()).contains(&number) => {
+                        let breakpoint = self.breakpoints.remove(number - 1);
+                        format!("Deleted breakpoint {}", breakpoint)
+                    }
+                    _ => format!("No breakpoint {}", argument),
+                },
+                "breakpoints" => self.list_breakpoints(),
+                "regs" | "r" => self.registers(),
+                "trace" => match argument.as_str() {
+                    "" => self.trace_listing(10),
+                    text => match text.parse() {
+                        Ok(count) => self.trace_listing(count),
+                        Err(_) => format!("Invalid count: {}", argument),
+                    },
+                },
+                "output" | "o" => self
+                    .output
+                    .iter()
+                    .map(u64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(","),
+                "quit" | "q" => break,
+                _ => format!("Unknown command: {}", command),
+            };
+
+            if !reply.is_empty() {
+                writeln!(output, "{}", reply)?;
+            }
+        }
+
+        output.flush()
+    }
+
+    /// Takes one step, recording it for `back`. False if the program has
+    /// halted or the next instruction cannot run.
+    fn step(&mut self) -> bool {
+        if self.fault().is_some() {
+            return false;
+        }
+
+        let snapshot = self.snapshot();
+        if !self.computer.step(&mut self.output) {
+            return false;
+        }
+        self.trace.push(snapshot);
+        true
+    }
+
+    /// Undoes the last step, if any.
+    fn back(&mut self) -> bool {
+        let Some(snapshot) = self.trace.pop() else {
+            return false;
+        };
+        self.computer.registers = snapshot.registers;
+        self.computer.pos = snapshot.pos;
+        self.output.truncate(snapshot.output_len);
+        true
+    }
+
+    /// Steps until a breakpoint fires, the program stops or the step limit
+    /// runs out. The first step is always taken, so continuing from a
+    /// breakpoint moves on.
+    fn resume(&mut self) -> String {
+        for taken in 0..STEP_LIMIT {
+            let before = self.snapshot();
+            if !self.step() {
+                return self.after_steps(taken, taken + 1);
+            }
+
+            let hit: Vec<String> = self
+                .breakpoints
+                .iter()
+                .enumerate()
+                .filter(|(_, breakpoint)| breakpoint.hit(&before, &self.computer))
+                .map(|(index, breakpoint)| format!("Breakpoint {}, {}", index + 1, breakpoint))
+                .collect();
+            if !hit.is_empty() {
+                return format!("{}\n{}", hit.join("\n"), self.location());
+            }
+        }
+
+        format!("Stopped after {} steps\n{}", STEP_LIMIT, self.location())
+    }
+
+    /// Reports where stepping stopped, and why if it stopped short.
+    fn after_steps(&self, taken: usize, wanted: usize) -> String {
+        if taken == wanted {
+            self.location()
+        } else if let Some(fault) = self.fault() {
+            format!("{}\n{}", fault, self.location())
+        } else {
+            format!(
+                "Halted after {} steps\n{}",
+                self.trace.len(),
+                self.location()
+            )
+        }
+    }
+
+    fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> String {
+        self.breakpoints.push(breakpoint);
+        format!("Breakpoint {}, {}", self.breakpoints.len(), breakpoint)
+    }
+
+    fn list_breakpoints(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return "No breakpoints".to_string();
+        }
+        self.breakpoints
+            .iter()
+            .enumerate()
+            .map(|(index, breakpoint)| format!("{}: {}", index + 1, breakpoint))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn registers(&self) -> String {
+        self.computer
+            .registers
+            .iter()
+            .zip(REGISTERS)
+            .map(|(value, name)| format!("{} = {} 0o{:o} 0b{:b}", name, value, value, value))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The last `count` steps, each with the registers before it.
+    fn trace_listing(&self, count: usize) -> String {
+        if self.trace.is_empty() {
+            return "No steps taken".to_string();
+        }
+        let start = self.trace.len().saturating_sub(count);
+        self.trace[start..]
+            .iter()
+            .enumerate()
+            .map(|(index, snapshot)| {
+                let instruction = Instruction::decode(&self.computer.program, snapshot.pos)
+                    .expect("traced steps decode");
+                format!(
+                    "#{} {:02}: {:<8} A={} B={} C={}",
+                    start + index + 1,
+                    snapshot.pos,
+                    instruction.to_string(),
+                    snapshot.registers[0],
+                    snapshot.registers[1],
+                    snapshot.registers[2]
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn current(&self) -> Option<Instruction> {
+        Instruction::decode(&self.computer.program, self.computer.pos)
+    }
+
+    /// Why the next instruction cannot run, if the program has not halted
+    /// but the computer would crash on it.
+    fn fault(&self) -> Option<String> {
+        match self.current() {
+            Some(Instruction {
+                operand: Operand::Reserved,
+                ..
+            }) => Some("Reserved combo operand".to_string()),
+            Some(_) => None,
+            None if self.computer.pos + 1 < self.computer.program.len() => Some(format!(
+                "Invalid opcode {}",
+                self.computer.program[self.computer.pos]
+            )),
+            None => None,
+        }
+    }
+
+    /// The next instruction, or a note that the program has halted.
+    fn location(&self) -> String {
+        let pos = self.computer.pos;
+        match self.current() {
+            Some(instruction) => format!("{:02}: {}", instruction.address, instruction),
+            None if pos + 1 < self.computer.program.len() => {
+                format!("{:02}: invalid opcode {}", pos, self.computer.program[pos])
+            }
+            None => format!("{:02}: halted", pos),
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.computer.registers.clone(),
+            pos: self.computer.pos,
+            output_len: self.output.len(),
+        }
+    }
+}
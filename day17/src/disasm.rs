@@ -0,0 +1,208 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::Computer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Adv,
+    Bxl,
+    Bst,
+    Jnz,
+    Bxc,
+    Out,
+    Bdv,
+    Cdv,
+}
+
+pub const OPCODES: [Opcode; 8] = [
+    Opcode::Adv,
+    Opcode::Bxl,
+    Opcode::Bst,
+    Opcode::Jnz,
+    Opcode::Bxc,
+    Opcode::Out,
+    Opcode::Bdv,
+    Opcode::Cdv,
+];
+
+impl Opcode {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Opcode::Adv => "adv",
+            Opcode::Bxl => "bxl",
+            Opcode::Bst => "bst",
+            Opcode::Jnz => "jnz",
+            Opcode::Bxc => "bxc",
+            Opcode::Out => "out",
+            Opcode::Bdv => "bdv",
+            Opcode::Cdv => "cdv",
+        }
+    }
+
+    /// Whether the operand is a combo operand rather than a literal.
+    pub fn takes_combo(self) -> bool {
+        matches!(
+            self,
+            Opcode::Adv | Opcode::Bst | Opcode::Out | Opcode::Bdv | Opcode::Cdv
+        )
+    }
+}
+
+/// An operand as the instruction reads it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Literal(u64),
+    /// Register A, B or C, by index
+    Register(usize),
+    /// Combo operand 7, which is not valid
+    Reserved,
+    /// `bxc` reads no operand
+    Ignored,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Literal(value) => write!(f, "{}", value),
+            Operand::Register(index) => write!(f, "{}", ["A", "B", "C"][*index]),
+            Operand::Reserved => write!(f, "<reserved 7>"),
+            Operand::Ignored => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: usize,
+    pub opcode: Opcode,
+    pub operand: Operand,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.operand {
+            Operand::Ignored => write!(f, "{}", self.opcode.mnemonic()),
+            operand => write!(f, "{} {}", self.opcode.mnemonic(), operand),
+        }
+    }
+}
+
+impl Instruction {
+    /// Decodes the instruction at `address` of `program`, if a whole one
+    /// starts there.
+    pub fn decode(program: &[u64], address: usize) -> Option<Self> {
+        let opcode = *OPCODES.get(*program.get(address)? as usize)?;
+        let raw = *program.get(address + 1)?;
+
+        let operand = if opcode == Opcode::Bxc {
+            Operand::Ignored
+        } else if !opcode.takes_combo() {
+            Operand::Literal(raw)
+        } else {
+            match raw {
+                0..=3 => Operand::Literal(raw),
+                4..=6 => Operand::Register(raw as usize - 4),
+                _ => Operand::Reserved,
+            }
+        };
+
+        Some(Instruction {
+            address,
+            opcode,
+            operand,
+        })
+    }
+
+    /// The instruction as a pseudo-code statement.
+    fn statement(&self) -> String {
+        let register = |index: usize| ["A", "B", "C"][index];
+        let divide = |target: usize| format!("{} = A / (2^{})", register(target), self.operand);
+
+        match self.opcode {
+            Opcode::Adv => divide(0),
+            Opcode::Bdv => divide(1),
+            Opcode::Cdv => divide(2),
+            Opcode::Bxl => format!("B = B ^ {}", self.operand),
+            Opcode::Bst => format!("B = {} % 8", self.operand),
+            Opcode::Bxc => "B = B ^ C".to_string(),
+            Opcode::Out => format!("output ({} % 8)", self.operand),
+            Opcode::Jnz => format!("if A != 0 goto L{}", self.operand),
+        }
+    }
+}
+
+impl Computer {
+    /// Decodes the program two numbers at a time from the start. A trailing
+    /// opcode without operand is dropped, as the computer halts before it.
+    pub fn disassemble(&self) -> Vec<Instruction> {
+        (0..self.program.len())
+            .step_by(2)
+            .map_while(|address| Instruction::decode(&self.program, address))
+            .collect()
+    }
+
+    /// The program as one `address: instruction` line each.
+    pub fn disassembly(&self) -> String {
+        self.disassemble()
+            .iter()
+            .map(|instruction| format!("{:02}: {}\n", instruction.address, instruction))
+            .collect()
+    }
+
+    /// Turns the program into pseudo-code.
+    ///
+    /// A program whose only jump is a final `jnz 0` becomes a loop, like the
+    /// puzzle inputs. Since the jump comes last the body always runs once, so
+    /// the loop checks `A` at the end. Other jumps are kept as labelled gotos.
+    pub fn decompile(&self) -> String {
+        let instructions = self.disassemble();
+        let jumps: Vec<&Instruction> = instructions
+            .iter()
+            .filter(|instruction| instruction.opcode == Opcode::Jnz)
+            .collect();
+
+        let single_loop = match jumps.as_slice() {
+            [jump] => {
+                jump.operand == Operand::Literal(0)
+                    && Some(jump.address) == instructions.last().map(|last| last.address)
+            }
+            _ => false,
+        };
+
+        let mut code = String::new();
+        if single_loop {
+            code.push_str("do {\n");
+            for instruction in &instructions[..instructions.len() - 1] {
+                code.push_str(&format!("    {}\n", instruction.statement()));
+            }
+            code.push_str("} while A != 0\n");
+            return code;
+        }
+
+        let targets: BTreeSet<usize> = jumps
+            .iter()
+            .filter_map(|jump| match jump.operand {
+                Operand::Literal(target) => Some(target as usize),
+                _ => None,
+            })
+            .collect();
+
+        for instruction in &instructions {
+            if targets.contains(&instruction.address) {
+                code.push_str(&format!("L{}:\n", instruction.address));
+            }
+            code.push_str(&format!("    {}\n", instruction.statement()));
+        }
+        // jumps past the end halt, jumps to odd addresses read the program
+        // shifted by one
+        for &target in &targets {
+            if target >= instructions.len() * 2 {
+                code.push_str(&format!("L{}:\n    halt\n", target));
+            } else if target % 2 == 1 {
+                code.push_str(&format!("L{}: misaligned, see the disassembly\n", target));
+            }
+        }
+        code
+    }
+}
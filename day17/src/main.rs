@@ -1,4 +1,11 @@
 use itertools::Itertools;
+use std::env;
+use std::io;
+use std::process;
+
+mod asm;
+mod debugger;
+mod disasm;
 
 #[derive(Debug, Clone)]
 struct Computer {
@@ -32,38 +39,61 @@ impl Computer {
 
     fn run(&mut self) -> Vec<u64> {
         let mut output = Vec::new();
+        while self.step(&mut output) {}
+        output
+    }
 
-        while self.pos + 1 < self.program.len() {
-            let (instruction, operand) = (self.program[self.pos], self.program[self.pos + 1]);
-            let combo = self.operand(operand);
+    /// Executes the instruction at `pos`, or returns false if the program
+    /// has halted.
+    fn step(&mut self, output: &mut Vec<u64>) -> bool {
+        if self.pos + 1 >= self.program.len() {
+            return false;
+        }
 
-            match instruction {
-                0 => self.registers[0] = self.registers[0] >> combo,
-                1 => self.registers[1] ^= operand,
-                2 => self.registers[1] = combo % 8,
-                3 => if self.registers[0] != 0 {
+        let (instruction, operand) = (self.program[self.pos], self.program[self.pos + 1]);
+        // literal operands may be 7, which is only reserved as a combo
+        let combo = match instruction {
+            1 | 3 | 4 => operand,
+            _ => self.operand(operand),
+        };
+
+        match instruction {
+            0 => self.registers[0] = divide(self.registers[0], combo),
+            1 => self.registers[1] ^= operand,
+            2 => self.registers[1] = combo % 8,
+            3 => {
+                if self.registers[0] != 0 {
                     self.pos = operand as usize;
-                    continue;
-                },
-                4 => self.registers[1] ^= self.registers[2],
-                5 => output.push(combo % 8),
-                6 => self.registers[1] = self.registers[0] >> combo,
-                7 => self.registers[2] = self.registers[0] >> combo,
-                _ => panic!("Invalid instruction: {instruction}"),
+                    return true;
+                }
             }
-
-            self.pos += 2;
+            4 => self.registers[1] ^= self.registers[2],
+            5 => output.push(combo % 8),
+            6 => self.registers[1] = divide(self.registers[0], combo),
+            7 => self.registers[2] = divide(self.registers[0], combo),
+            _ => panic!("Invalid instruction: {instruction}"),
         }
-        output
+
+        self.pos += 2;
+        true
     }
 }
 
+/// `value / 2^exponent`, which is 0 for exponents of 64 or more.
+fn divide(value: u64, exponent: u64) -> u64 {
+    u32::try_from(exponent)
+        .ok()
+        .and_then(|exponent| value.checked_shr(exponent))
+        .unwrap_or(0)
+}
+
 fn find_target_sequence(program: &[u64]) -> Option<u64> {
     let mut possible_values = vec![0u64];
-    
+
     // Work backwards through the output sequence
     for target in program.iter().rev() {
-        let next_values = possible_values.iter()
+        let next_values = possible_values
+            .iter()
             .flat_map(|&current| {
                 (0..8).filter_map(move |bits| {
                     let new_value = (current << 3) | bits;
@@ -71,14 +101,14 @@ fn find_target_sequence(program: &[u64]) -> Option<u64> {
                 })
             })
             .collect();
-            
+
         possible_values = next_values;
-        
+
         if possible_values.is_empty() {
             return None;
         }
     }
-    
+
     possible_values.into_iter().min()
 }
 
@@ -87,7 +117,7 @@ fn verify_step(value: u64, target: u64) -> Option<u64> {
     let after_xor5 = original ^ 5;
     let shifted = value >> after_xor5;
     let after_xor6 = after_xor5 ^ 6;
-    
+
     if (after_xor6 ^ shifted) % 8 == target {
         Some(original)
     } else {
@@ -95,13 +125,245 @@ fn verify_step(value: u64, target: u64) -> Option<u64> {
     }
 }
 
+/// Usage: day17 [--disasm] [--decompile] [--assemble FILE] [--debug]
+///
+/// `--debug` steps through the input under commands read from stdin.
 fn main() {
     let input = std::fs::read_to_string("input.txt").expect("Failed to read input file");
     let computer = Computer::from_input(&input);
-    
+
+    // the tools replace the puzzle answers
+    let mut used_tool = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        used_tool = true;
+        match arg.as_str() {
+            "--disasm" => print!("{}", computer.disassembly()),
+            "--decompile" => print!("{}", computer.decompile()),
+            "--assemble" => {
+                let path = args.next().expect("--assemble needs a file");
+                let source = std::fs::read_to_string(path).expect("Failed to read source file");
+                match asm::assemble(&source) {
+                    Ok(output) => print!("{output}"),
+                    Err(error) => {
+                        eprintln!("{error}");
+                        process::exit(1);
+                    }
+                }
+            }
+            "--debug" => {
+                let mut debugger = debugger::Debugger::new(computer.clone());
+                debugger
+                    .run(io::stdin().lock(), &mut io::stdout())
+                    .expect("Failed to run debugger");
+            }
+            _ => {
+                eprintln!("Unknown option: {arg}");
+                eprintln!("Usage: day17 [--disasm] [--decompile] [--assemble FILE] [--debug]");
+                process::exit(2);
+            }
+        }
+    }
+    if used_tool {
+        return;
+    }
+
     let mut part1 = computer.clone();
     println!("Part 1: {}", part1.run().iter().join(","));
-    
+
     let initial_value = find_target_sequence(&computer.program).unwrap();
     println!("Part 2: {initial_value}");
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let input = std::fs::read_to_string("test.txt").unwrap();
+        let mut computer = Computer::from_input(&input);
+        assert_eq!(computer.run(), [4, 6, 3, 5, 6, 3, 5, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let input = std::fs::read_to_string("input.txt").unwrap();
+        let computer = Computer::from_input(&input);
+
+        assert_eq!(
+            computer.disassembly(),
+            "00: bst A\n02: bxl 5\n04: cdv B\n06: bxl 6\n08: adv 3\n10: bxc\n12: out B\n14: jnz 0\n"
+        );
+        assert_eq!(
+            computer.decompile(),
+            "\
+do {
+    B = A % 8
+    B = B ^ 5
+    C = A / (2^B)
+    B = B ^ 6
+    A = A / (2^3)
+    B = B ^ C
+    output (B % 8)
+} while A != 0
+"
+        );
+    }
+
+    #[test]
+    fn test_decompile_jumps() {
+        let computer = Computer::from_input(
+            "Register A: 0 Register B: 0 Register C: 0 Program: 3,4,5,7,3,9,1",
+        );
+        assert_eq!(
+            computer.disassembly(),
+            "00: jnz 4\n02: out <reserved 7>\n04: jnz 9\n"
+        );
+        assert_eq!(
+            computer.decompile(),
+            "    if A != 0 goto L4\n    output (<reserved 7> % 8)\nL4:\n    if A != 0 goto L9\nL9:\n    halt\n"
+        );
+    }
+
+    #[test]
+    fn test_assemble() {
+        let source = "\
+; the first example, printing A's octal digits
+.a 729
+loop:
+    adv 1       ; A = A / 2
+    out A
+    jnz loop
+";
+        let output = asm::assemble(source).unwrap();
+        assert_eq!(
+            output,
+            "Register A: 729\nRegister B: 0\nRegister C: 0\n\nProgram: 0,1,5,4,3,0\n"
+        );
+        assert_eq!(
+            Computer::from_input(&output).run(),
+            [4, 6, 3, 5, 6, 3, 5, 2, 1, 0]
+        );
+
+        // the disassembly assembles back into an equivalent program; only the
+        // operand `bxc` ignores is lost
+        let input = std::fs::read_to_string("input.txt").unwrap();
+        let computer = Computer::from_input(&input);
+        let source = format!(".a {}\n{}", computer.registers[0], computer.disassembly());
+        let assembled = Computer::from_input(&asm::assemble(&source).unwrap());
+        assert_eq!(assembled.disassembly(), computer.disassembly());
+        assert_eq!(assembled.clone().run(), computer.clone().run());
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let error = |source: &str| asm::assemble(source).unwrap_err().to_string();
+
+        assert_eq!(error("adv 1\nout 7"), "line 2: Combo operand 7 is reserved");
+        assert_eq!(error("bxl 8"), "line 1: Operand 8 does not fit in 3 bits");
+        assert_eq!(error("out D"), "line 1: Invalid operand D");
+        assert_eq!(error("jnz end"), "line 1: Unknown label end");
+        assert_eq!(error("mul 3"), "line 1: Unknown instruction mul");
+        assert_eq!(error("out"), "line 1: out needs an operand");
+        assert_eq!(error("a: adv 1\na: adv 1"), "line 2: Label a defined twice");
+        assert_eq!(
+            error("bxl 1\nbxl 1\nbxl 1\nbxl 1\nfar: bxl 1\njnz far"),
+            "line 6: Label far is at address 8, out of reach of jnz"
+        );
+    }
+
+    #[test]
+    fn test_debugger() {
+        let input = std::fs::read_to_string("test.txt").unwrap();
+        let mut debugger = debugger::Debugger::new(Computer::from_input(&input));
+        let script = "\
+s
+r
+break 4
+break A < 50
+watch B
+c
+delete 1
+c
+o
+trace 2
+b 3
+o
+back 100
+s 1000
+bogus
+";
+        let mut output = Vec::new();
+        debugger.run(script.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+00: adv 1
+02: out A
+A = 364 0o554 0b101101100
+B = 0 0o0 0b0
+C = 0 0o0 0b0
+Breakpoint 1, at 04
+Breakpoint 2, when A < 50
+Breakpoint 3, when B changes
+Breakpoint 1, at 04
+04: jnz 0
+Deleted breakpoint at 04
+Breakpoint 1, when A < 50
+02: out A
+4,6,3
+#9 04: jnz 0    A=91 B=0 C=0
+#10 00: adv 1    A=91 B=0 C=0
+02: out A
+4,6
+At the start
+00: adv 1
+Halted after 30 steps
+06: halted
+Unknown command: bogus
+"
+        );
+        assert_eq!(debugger.output, [4, 6, 3, 5, 6, 3, 5, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_debugger_faults() {
+        // shifting by 64 or more empties the register
+        let source = ".a 729\n.b 64\nadv B\nout A";
+        let computer = Computer::from_input(&asm::assemble(source).unwrap());
+        let mut debugger = debugger::Debugger::new(computer);
+        let mut output = Vec::new();
+        debugger.run("s 5\nr".as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+00: adv B
+Halted after 2 steps
+04: halted
+A = 0 0o0 0b0
+B = 64 0o100 0b1000000
+C = 0 0o0 0b0
+"
+        );
+        assert_eq!(debugger.output, [0]);
+
+        let computer =
+            Computer::from_input("Register A: 1\nRegister B: 0\nRegister C: 0\n\nProgram: 5,4,9,0");
+        let mut debugger = debugger::Debugger::new(computer);
+        let mut output = Vec::new();
+        debugger.run("s\ns\nc".as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+00: out A
+02: invalid opcode 9
+Invalid opcode 9
+02: invalid opcode 9
+Invalid opcode 9
+02: invalid opcode 9
+"
+        );
+    }
+}